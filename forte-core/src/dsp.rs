@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+mod effects;
+mod limiter;
+mod loudness;
+
+pub use effects::{DSPEffect, EffectChain};
+
+/// `Unlinked` limits every channel on its own, `Linked` applies the same gain to
+/// all channels and looks ahead for true peaks. `Unlinked` is the default so
+/// configs from before the linked mode keep sounding the same.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum LimiterMode {
+    #[default]
+    Unlinked,
+    Linked,
+}
+
+impl std::fmt::Display for LimiterMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LimiterMode::Unlinked => write!(f, "Unlinked"),
+            LimiterMode::Linked => write!(f, "Linked (True Peak)"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DSPLimiterSettings {
+    pub enabled: bool,
+    pub mode: LimiterMode,
+    /// In linked mode, the time over which the gain is lowered before a peak,
+    /// up to the lookahead
+    pub attack_ms: u16,
+    pub release_ms: u16,
+    pub ceiling_db: f32,
+    pub lookahead_ms: f32,
+}
+
+impl Default for DSPLimiterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: LimiterMode::Unlinked,
+            attack_ms: 30,
+            release_ms: 80,
+            ceiling_db: -0.3,
+            lookahead_ms: 5.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DSPLoudnessSettings {
+    pub enabled: bool,
+    pub target_lufs: f32,
+    pub true_peak_ceiling_db: f32,
+}
+
+impl Default for DSPLoudnessSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_lufs: -14.0,
+            true_peak_ceiling_db: -1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DSPPeakNormalizationSettings {
+    pub enabled: bool,
+    pub target_db: f32,
+}
+
+impl Default for DSPPeakNormalizationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_db: -0.1,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct DSPSettings {
+    /// Applied in order, before the limiter
+    pub effects: Vec<DSPEffect>,
+    pub limiter: DSPLimiterSettings,
+    pub loudness: DSPLoudnessSettings,
+    pub peak_normalization: DSPPeakNormalizationSettings,
+}
+
+impl DSPSettings {
+    /// Whether the normalization needs a second pass over the output
+    pub fn is_two_pass(&self) -> bool {
+        self.loudness.enabled || self.peak_normalization.enabled
+    }
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+pub struct ForteAudioDSP {
+    channels: u16,
+    settings: DSPSettings,
+    effects: EffectChain,
+    limiter: Option<Vec<limiter::AudioLimiter>>,
+    lookahead_limiter: Option<limiter::LookaheadLimiter>,
+    loudness: Option<(loudness::LoudnessMeter, loudness::TruePeakMeter)>,
+}
+
+impl ForteAudioDSP {
+    pub fn new(channels: u16, sample_rate: u32, settings: DSPSettings) -> Self {
+        let effects = EffectChain::new(&settings.effects, channels, sample_rate);
+
+        let limiter = if settings.limiter.enabled && settings.limiter.mode == LimiterMode::Unlinked
+        {
+            let mut v = Vec::new();
+            for _ in 0..channels {
+                v.push(limiter::AudioLimiter::new(settings.limiter));
+            }
+            Some(v)
+        } else {
+            None
+        };
+
+        let lookahead_limiter =
+            if settings.limiter.enabled && settings.limiter.mode == LimiterMode::Linked {
+                Some(limiter::LookaheadLimiter::new(
+                    channels,
+                    sample_rate,
+                    settings.limiter,
+                ))
+            } else {
+                None
+            };
+
+        let loudness = if settings.loudness.enabled {
+            Some((
+                loudness::LoudnessMeter::new(channels, sample_rate),
+                loudness::TruePeakMeter::new(channels),
+            ))
+        } else {
+            None
+        };
+
+        Self {
+            channels,
+            settings,
+            effects,
+            limiter,
+            lookahead_limiter,
+            loudness,
+        }
+    }
+
+    /// Whether the output has to be analyzed completely before it can be written.
+    /// In that case `normalization_gain` returns the gain for the second pass.
+    pub fn is_two_pass(&self) -> bool {
+        self.settings.is_two_pass()
+    }
+
+    pub fn settings(&self) -> &DSPSettings {
+        &self.settings
+    }
+
+    /// Processes a block of samples. The lookahead limiter delays the audio, so the
+    /// block may get shorter and the rest has to be read with `flush`.
+    pub fn process(&mut self, vec: &mut Vec<f32>) {
+        self.effects.process(vec);
+
+        if let Some(limiter) = self.limiter.as_mut() {
+            for (i, s) in vec.iter_mut().enumerate() {
+                *s = limiter[i % self.channels as usize].limit(*s);
+            }
+        }
+
+        if let Some(limiter) = self.lookahead_limiter.as_mut() {
+            limiter.process(vec);
+        }
+
+        self.measure(vec);
+    }
+
+    pub fn flush(&mut self) -> Vec<f32> {
+        let vec = match self.lookahead_limiter.as_mut() {
+            Some(limiter) => limiter.flush(),
+            None => Vec::new(),
+        };
+        self.measure(&vec);
+        vec
+    }
+
+    fn measure(&mut self, vec: &[f32]) {
+        if let Some((meter, true_peak)) = self.loudness.as_mut() {
+            meter.process(vec);
+            true_peak.process(vec);
+        }
+    }
+
+    pub fn normalization_gain(&self) -> f32 {
+        let mut gain = 1.0;
+
+        if let Some((meter, true_peak)) = &self.loudness {
+            let settings = self.settings.loudness;
+            if let Some(loudness) = meter.integrated() {
+                info!("Measured integrated loudness: {:.1} LUFS", loudness);
+                gain = db_to_gain(settings.target_lufs - loudness as f32);
+            }
+
+            // The gain is lowered to keep the true peak under the ceiling
+            let ceiling = db_to_gain(settings.true_peak_ceiling_db);
+            if true_peak.peak() * gain > ceiling {
+                gain = ceiling / true_peak.peak();
+            }
+        }
+
+        gain
+    }
+}
@@ -0,0 +1,254 @@
+use super::db_to_gain;
+use fundsp::hacker32::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum DSPEffect {
+    Equalizer {
+        frequency: f32,
+        q: f32,
+        gain_db: f32,
+    },
+    Compressor {
+        threshold_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        makeup_db: f32,
+    },
+    Reverb {
+        room_size: f32,
+        time: f32,
+        wet: f32,
+    },
+    HighPass {
+        frequency: f32,
+        q: f32,
+    },
+    LowPass {
+        frequency: f32,
+        q: f32,
+    },
+    DCBlocker {
+        frequency: f32,
+    },
+    StereoWidth {
+        width: f32,
+    },
+}
+
+impl DSPEffect {
+    /// Every effect with its default parameters, in the order they are offered in the UI
+    pub fn all() -> [DSPEffect; 7] {
+        [
+            DSPEffect::Equalizer {
+                frequency: 1000.0,
+                q: 1.0,
+                gain_db: 0.0,
+            },
+            DSPEffect::Compressor {
+                threshold_db: -18.0,
+                ratio: 4.0,
+                attack_ms: 10.0,
+                release_ms: 100.0,
+                makeup_db: 0.0,
+            },
+            DSPEffect::Reverb {
+                room_size: 20.0,
+                time: 2.0,
+                wet: 0.2,
+            },
+            DSPEffect::HighPass {
+                frequency: 30.0,
+                q: 0.707,
+            },
+            DSPEffect::LowPass {
+                frequency: 16000.0,
+                q: 0.707,
+            },
+            DSPEffect::DCBlocker { frequency: 10.0 },
+            DSPEffect::StereoWidth { width: 1.0 },
+        ]
+    }
+}
+
+impl std::fmt::Display for DSPEffect {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DSPEffect::Equalizer { .. } => write!(f, "Parametric EQ"),
+            DSPEffect::Compressor { .. } => write!(f, "Compressor"),
+            DSPEffect::Reverb { .. } => write!(f, "Reverb"),
+            DSPEffect::HighPass { .. } => write!(f, "High-pass Filter"),
+            DSPEffect::LowPass { .. } => write!(f, "Low-pass Filter"),
+            DSPEffect::DCBlocker { .. } => write!(f, "DC Blocker"),
+            DSPEffect::StereoWidth { .. } => write!(f, "Stereo Width"),
+        }
+    }
+}
+
+struct Compressor {
+    threshold_db: f32,
+    slope: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    makeup: f32,
+    envelope_db: f32,
+}
+
+impl Compressor {
+    fn process(&mut self, frame: &mut [f32]) {
+        // Linked detection, so all channels get the same gain reduction
+        let peak = frame.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        let level_db = 20.0 * peak.max(1e-9).log10();
+
+        let coef = if level_db > self.envelope_db {
+            self.attack_coef
+        } else {
+            self.release_coef
+        };
+        self.envelope_db = level_db + (self.envelope_db - level_db) * coef;
+
+        let over = (self.envelope_db - self.threshold_db).max(0.0);
+        let gain = db_to_gain(-over * self.slope) * self.makeup;
+        for s in frame.iter_mut() {
+            *s *= gain;
+        }
+    }
+}
+
+enum EffectProcessor {
+    // One mono node for every channel
+    Filter(Vec<Box<dyn AudioUnit32>>),
+    Reverb {
+        node: Box<dyn AudioUnit32>,
+        wet: f32,
+    },
+    Compressor(Compressor),
+    StereoWidth(f32),
+}
+
+fn time_coef(ms: f32, sample_rate: u32) -> f32 {
+    (-1000.0 / (ms.max(0.01) * sample_rate as f32)).exp()
+}
+
+impl EffectProcessor {
+    fn new(effect: &DSPEffect, channels: u16, sample_rate: u32) -> Self {
+        let filter = |make: &dyn Fn() -> Box<dyn AudioUnit32>| {
+            let mut nodes = Vec::new();
+            for _ in 0..channels {
+                let mut node = make();
+                node.set_sample_rate(sample_rate.into());
+                nodes.push(node);
+            }
+            EffectProcessor::Filter(nodes)
+        };
+
+        match *effect {
+            DSPEffect::Equalizer {
+                frequency,
+                q,
+                gain_db,
+            } => filter(&|| Box::new(bell_hz(frequency, q, db_to_gain(gain_db)))),
+            DSPEffect::HighPass { frequency, q } => filter(&|| Box::new(highpass_hz(frequency, q))),
+            DSPEffect::LowPass { frequency, q } => filter(&|| Box::new(lowpass_hz(frequency, q))),
+            DSPEffect::DCBlocker { frequency } => filter(&|| Box::new(dcblock_hz(frequency))),
+            DSPEffect::Reverb {
+                room_size,
+                time,
+                wet,
+            } => {
+                let mut node: Box<dyn AudioUnit32> =
+                    Box::new(reverb_stereo(room_size.into(), time.into()));
+                node.set_sample_rate(sample_rate.into());
+                EffectProcessor::Reverb { node, wet }
+            }
+            DSPEffect::Compressor {
+                threshold_db,
+                ratio,
+                attack_ms,
+                release_ms,
+                makeup_db,
+            } => EffectProcessor::Compressor(Compressor {
+                threshold_db,
+                slope: 1.0 - 1.0 / ratio.max(1.0),
+                attack_coef: time_coef(attack_ms, sample_rate),
+                release_coef: time_coef(release_ms, sample_rate),
+                makeup: db_to_gain(makeup_db),
+                envelope_db: -120.0,
+            }),
+            DSPEffect::StereoWidth { width } => EffectProcessor::StereoWidth(width),
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        match self {
+            EffectProcessor::Filter(nodes) => {
+                let mut out = [0.0];
+                for (node, s) in nodes.iter_mut().zip(frame.iter_mut()) {
+                    node.tick(&[*s], &mut out);
+                    *s = out[0];
+                }
+            }
+            EffectProcessor::Reverb { node, wet } => {
+                // The reverb is always stereo, mono audio is sent to both sides
+                let input = match frame {
+                    [l, r] => [*l, *r],
+                    _ => [frame[0], frame[0]],
+                };
+                let mut out = [0.0; 2];
+                node.tick(&input, &mut out);
+
+                if frame.len() == 2 {
+                    frame[0] = input[0] * (1.0 - *wet) + out[0] * *wet;
+                    frame[1] = input[1] * (1.0 - *wet) + out[1] * *wet;
+                } else {
+                    frame[0] = input[0] * (1.0 - *wet) + (out[0] + out[1]) * 0.5 * *wet;
+                }
+            }
+            EffectProcessor::Compressor(compressor) => compressor.process(frame),
+            EffectProcessor::StereoWidth(width) => {
+                if let [l, r] = frame {
+                    let mid = (*l + *r) * 0.5;
+                    let side = (*l - *r) * 0.5 * *width;
+                    *l = mid + side;
+                    *r = mid - side;
+                }
+            }
+        }
+    }
+}
+
+/// Runs interleaved audio through a list of effects, in order
+pub struct EffectChain {
+    channels: usize,
+    effects: Vec<EffectProcessor>,
+}
+
+impl EffectChain {
+    pub fn new(effects: &[DSPEffect], channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels: channels as usize,
+            effects: effects
+                .iter()
+                .map(|e| EffectProcessor::new(e, channels, sample_rate))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.effects.is_empty() {
+            return;
+        }
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for effect in self.effects.iter_mut() {
+                effect.process(frame);
+            }
+        }
+    }
+}
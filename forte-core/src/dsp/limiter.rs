@@ -0,0 +1,140 @@
+use super::loudness::{TruePeakMeter, TRUE_PEAK_LATENCY};
+use super::{db_to_gain, DSPLimiterSettings};
+use fundsp::hacker32::*;
+use std::collections::VecDeque;
+
+pub struct AudioLimiter {
+    limiter: An<Limiter<f32, U1, (f32, f32)>>,
+}
+
+impl AudioLimiter {
+    pub fn new(settings: DSPLimiterSettings) -> Self {
+        Self {
+            limiter: limiter((
+                settings.attack_ms as f32 / 1000.0,
+                settings.release_ms as f32 / 1000.0,
+            )),
+        }
+    }
+
+    pub fn limit(&mut self, input: f32) -> f32 {
+        self.limiter.tick(&Frame::from([input]))[0]
+    }
+}
+
+/// Limiter that applies the same gain to every channel. The audio is delayed by the
+/// lookahead time, so the gain is already reduced when a peak arrives, and peaks are
+/// detected on the oversampled signal so the output stays under the ceiling.
+pub struct LookaheadLimiter {
+    channels: usize,
+    ceiling: f32,
+    lookahead: usize,
+    attack: usize,
+    release_coef: f32,
+    true_peak: TruePeakMeter,
+
+    delay: VecDeque<f32>,
+    // Monotonic queue of (frame, required gain) for the minimum over the lookahead
+    required: VecDeque<(usize, f32)>,
+    // The smoothed gain is the average of the envelope over the attack time
+    envelope: f32,
+    smoothing: VecDeque<f32>,
+    smoothing_sum: f64,
+
+    frame: usize,
+    skip: usize,
+}
+
+impl LookaheadLimiter {
+    pub fn new(channels: u16, sample_rate: u32, settings: DSPLimiterSettings) -> Self {
+        let channels = channels as usize;
+        let lookahead = std::cmp::max(
+            (settings.lookahead_ms * sample_rate as f32 / 1000.0) as usize,
+            1,
+        );
+        // The gain has to be down when the peak leaves the delay, so the attack
+        // can't be longer than the lookahead
+        let attack = ((settings.attack_ms as f32 * sample_rate as f32 / 1000.0) as usize)
+            .clamp(1, lookahead);
+        let latency = lookahead - 1 + TRUE_PEAK_LATENCY;
+        let release_samples = settings.release_ms as f32 / 1000.0 * sample_rate as f32;
+
+        Self {
+            channels,
+            ceiling: db_to_gain(settings.ceiling_db),
+            lookahead,
+            attack,
+            release_coef: (-1.0 / release_samples.max(1.0)).exp(),
+            true_peak: TruePeakMeter::new(channels as u16),
+
+            delay: VecDeque::from(vec![0.0; latency * channels]),
+            required: VecDeque::new(),
+            envelope: 1.0,
+            smoothing: VecDeque::from(vec![1.0; attack]),
+            smoothing_sum: attack as f64,
+
+            frame: 0,
+            skip: latency,
+        }
+    }
+
+    fn process_frame(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        let mut peak = 0.0f32;
+        for (channel, sample) in input.iter().enumerate() {
+            peak = peak.max(self.true_peak.process_sample(channel, *sample));
+        }
+        let required = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        while matches!(self.required.back(), Some((_, g)) if *g >= required) {
+            self.required.pop_back();
+        }
+        self.required.push_back((self.frame, required));
+        while matches!(self.required.front(), Some((f, _)) if f + self.lookahead <= self.frame) {
+            self.required.pop_front();
+        }
+        let target = self.required.front().map(|(_, g)| *g).unwrap_or(1.0);
+
+        self.envelope = if target < self.envelope {
+            target
+        } else {
+            target + (self.envelope - target) * self.release_coef
+        };
+
+        self.smoothing.push_back(self.envelope);
+        self.smoothing_sum += self.envelope as f64;
+        self.smoothing_sum -= self.smoothing.pop_front().unwrap_or(1.0) as f64;
+        let gain = (self.smoothing_sum / self.attack as f64) as f32;
+
+        self.delay.extend(input.iter());
+        let delayed = self.delay.drain(..self.channels);
+        if self.skip > 0 {
+            self.skip -= 1;
+        } else {
+            output.extend(delayed.map(|s| s * gain));
+        }
+
+        self.frame += 1;
+    }
+
+    pub fn process(&mut self, samples: &mut Vec<f32>) {
+        let mut output = Vec::with_capacity(samples.len());
+        for frame in samples.chunks_exact(self.channels) {
+            self.process_frame(frame, &mut output);
+        }
+        *samples = output;
+    }
+
+    /// Returns the samples still held back by the lookahead
+    pub fn flush(&mut self) -> Vec<f32> {
+        let mut output = Vec::new();
+        let silence = vec![0.0; self.channels];
+        for _ in 0..self.delay.len() / self.channels {
+            self.process_frame(&silence, &mut output);
+        }
+        output
+    }
+}
@@ -0,0 +1,214 @@
+use std::f64::consts::PI;
+
+// BS.1770 measures loudness over 400ms blocks that overlap by 75%
+const SUBBLOCKS_PER_BLOCK: usize = 4;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+/// How many samples the interpolated peaks lag behind the input
+pub const TRUE_PEAK_LATENCY: usize = TAPS_PER_PHASE / 2;
+
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let out = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input - self.a[0] * out + self.z[1];
+        self.z[1] = self.b[2] * input - self.a[1] * out;
+        out
+    }
+}
+
+// The K-weighting filter of BS.1770, recalculated for any sample rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let g = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(g / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, highpass]
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Measures the integrated loudness of interleaved audio, as described in EBU R128
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    subblock_len: usize,
+    subblock_pos: usize,
+    subblock_sum: f64,
+    subblocks: Vec<f64>,
+    blocks: Vec<f64>,
+    index: usize,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels: channels as usize,
+            filters: vec![k_weighting(sample_rate); channels as usize],
+            subblock_len: sample_rate as usize / 10,
+            subblock_pos: 0,
+            subblock_sum: 0.0,
+            subblocks: Vec::new(),
+            blocks: Vec::new(),
+            index: 0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        for sample in samples {
+            let mut s = *sample as f64;
+            for filter in self.filters[self.index].iter_mut() {
+                s = filter.process(s);
+            }
+            self.subblock_sum += s * s;
+
+            self.index += 1;
+            if self.index == self.channels {
+                self.index = 0;
+                self.subblock_pos += 1;
+                if self.subblock_pos == self.subblock_len {
+                    self.finish_subblock();
+                }
+            }
+        }
+    }
+
+    fn finish_subblock(&mut self) {
+        self.subblocks.push(self.subblock_sum);
+        self.subblock_sum = 0.0;
+        self.subblock_pos = 0;
+
+        if self.subblocks.len() >= SUBBLOCKS_PER_BLOCK {
+            let start = self.subblocks.len() - SUBBLOCKS_PER_BLOCK;
+            let sum: f64 = self.subblocks[start..].iter().sum();
+            self.blocks
+                .push(sum / (self.subblock_len * SUBBLOCKS_PER_BLOCK) as f64);
+            self.subblocks.remove(0);
+        }
+    }
+
+    /// Returns the gated integrated loudness in LUFS, `None` if the audio was silent
+    pub fn integrated(&self) -> Option<f64> {
+        let gated_mean = |threshold: f64| {
+            let gated: Vec<f64> = self
+                .blocks
+                .iter()
+                .copied()
+                .filter(|e| energy_to_lufs(*e) > threshold)
+                .collect();
+            if gated.is_empty() {
+                None
+            } else {
+                Some(gated.iter().sum::<f64>() / gated.len() as f64)
+            }
+        };
+
+        let relative_gate = energy_to_lufs(gated_mean(ABSOLUTE_GATE_LUFS)?) + RELATIVE_GATE_LU;
+        gated_mean(relative_gate.max(ABSOLUTE_GATE_LUFS)).map(energy_to_lufs)
+    }
+}
+
+/// Oversamples interleaved audio to find the true (inter-sample) peak
+pub struct TruePeakMeter {
+    channels: usize,
+    coefficients: Vec<f32>,
+    history: Vec<[f32; TAPS_PER_PHASE]>,
+    index: usize,
+    peak: f32,
+}
+
+impl TruePeakMeter {
+    pub fn new(channels: u16) -> Self {
+        // Windowed sinc interpolation filter, split into one phase per oversampled point
+        let len = OVERSAMPLING * TAPS_PER_PHASE;
+        let center = (len - 1) as f64 / 2.0;
+        let coefficients = (0..len)
+            .map(|i| {
+                let x = (i as f64 - center) / OVERSAMPLING as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / (len - 1) as f64).cos();
+                (sinc * window) as f32
+            })
+            .collect();
+
+        Self {
+            channels: channels as usize,
+            coefficients,
+            history: vec![[0.0; TAPS_PER_PHASE]; channels as usize],
+            index: 0,
+            peak: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        for sample in samples {
+            self.process_sample(self.index, *sample);
+            self.index = (self.index + 1) % self.channels;
+        }
+    }
+
+    /// Adds one sample of a channel and returns the highest of its oversampled values
+    pub fn process_sample(&mut self, channel: usize, sample: f32) -> f32 {
+        let history = &mut self.history[channel];
+        history.copy_within(0..TAPS_PER_PHASE - 1, 1);
+        history[0] = sample;
+
+        let mut peak = sample.abs();
+        for phase in 0..OVERSAMPLING {
+            let value: f32 = history
+                .iter()
+                .enumerate()
+                .map(|(k, s)| s * self.coefficients[k * OVERSAMPLING + phase])
+                .sum();
+            peak = peak.max(value.abs());
+        }
+
+        self.peak = self.peak.max(peak);
+        peak
+    }
+
+    /// The highest peak so far, as a linear value
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+}
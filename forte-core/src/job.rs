@@ -0,0 +1,226 @@
+use crate::errors::error_types::MIDIRendererError;
+use crate::settings::ForteConfig;
+use crate::xsynth::{
+    MIDIInput, MIDIRendererStatus, ManagerStatus, RenderStats, RenderThreadManager,
+};
+use crossbeam_channel::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{error, info};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RenderEvent {
+    /// The soundfonts were loaded and the MIDIs started rendering
+    SoundfontsLoaded,
+    /// The soundfont chain could not be loaded and the job was aborted
+    SoundfontError {
+        error: String,
+    },
+    /// Progress of a MIDI that is currently rendering
+    Progress {
+        id: usize,
+        time: f64,
+        voice_count: u64,
+    },
+    MIDIFinished {
+        id: usize,
+    },
+    /// The MIDI could not be loaded or its audio file could not be written
+    MIDIFailed {
+        id: usize,
+        error: String,
+    },
+    Cancelled,
+    /// Every MIDI in the job was processed, this is always the last event
+    Finished,
+}
+
+/// A list of MIDIs rendered with one config.
+///
+/// The job does nothing until `start` is called. After that it runs on its own
+/// thread and reports what it is doing through the returned channel, or through
+/// `progress` and `statuses` for callers that prefer polling.
+pub struct RenderJob {
+    config: ForteConfig,
+    manager: Arc<Mutex<RenderThreadManager>>,
+    statuses: Arc<Mutex<Vec<MIDIRendererStatus>>>,
+    cancelled: Arc<AtomicBool>,
+    driver: Option<JoinHandle<()>>,
+}
+
+impl RenderJob {
+    pub fn new(config: ForteConfig, midis: Vec<MIDIInput>) -> Result<Self, MIDIRendererError> {
+        info!("Creating new render job with {} MIDI(s)", midis.len());
+        let statuses = vec![MIDIRendererStatus::Idle; midis.len()];
+        let manager = RenderThreadManager::new(&config, midis)?;
+
+        Ok(Self {
+            config,
+            manager: Arc::new(Mutex::new(manager)),
+            statuses: Arc::new(Mutex::new(statuses)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            driver: None,
+        })
+    }
+
+    pub fn config(&self) -> &ForteConfig {
+        &self.config
+    }
+
+    pub fn start(&mut self) -> Receiver<RenderEvent> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        if self.driver.is_none() {
+            let manager = self.manager.clone();
+            let statuses = self.statuses.clone();
+            let cancelled = self.cancelled.clone();
+            self.driver = Some(thread::spawn(move || {
+                Self::drive(manager, statuses, cancelled, sender)
+            }));
+        }
+
+        receiver
+    }
+
+    fn drive(
+        manager: Arc<Mutex<RenderThreadManager>>,
+        statuses: Arc<Mutex<Vec<MIDIRendererStatus>>>,
+        cancelled: Arc<AtomicBool>,
+        sender: Sender<RenderEvent>,
+    ) {
+        let mut reported = vec![false; statuses.lock().unwrap().len()];
+        let mut started = false;
+
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                info!("Render job cancelled");
+                manager.lock().unwrap().cancel_all();
+                sender.send(RenderEvent::Cancelled).unwrap_or_default();
+                break;
+            }
+
+            let mut mgr = manager.lock().unwrap();
+            let finished = match mgr.status() {
+                ManagerStatus::LoadingSoundfonts => false,
+                ManagerStatus::SFLoadError => {
+                    error!("Invalid Soundfont chain. Aborting render.");
+                    let error = mgr
+                        .soundfont_error()
+                        .unwrap_or_else(|| "Invalid Soundfont chain".to_owned());
+                    mgr.cancel_all();
+                    sender
+                        .send(RenderEvent::SoundfontError { error })
+                        .unwrap_or_default();
+                    break;
+                }
+                ManagerStatus::SoundfontsFinished => {
+                    if !started {
+                        info!("Starting export");
+                        sender
+                            .send(RenderEvent::SoundfontsLoaded)
+                            .unwrap_or_default();
+                        started = true;
+                    }
+                    mgr.render();
+                    false
+                }
+                ManagerStatus::RenderingMIDIs => {
+                    mgr.spawn_next();
+                    false
+                }
+                ManagerStatus::RenderFinished => !mgr.spawn_next(),
+            };
+
+            let current = mgr.get_statuses();
+            let errors = mgr.get_errors();
+            for (id, stats) in mgr.get_stats().into_iter().enumerate() {
+                if let Some(stats) = stats {
+                    if current[id] == MIDIRendererStatus::Rendering {
+                        sender
+                            .send(RenderEvent::Progress {
+                                id,
+                                time: stats.time,
+                                voice_count: stats.voice_count,
+                            })
+                            .unwrap_or_default();
+                    }
+                }
+            }
+
+            for (id, status) in current.iter().enumerate() {
+                if reported[id] {
+                    continue;
+                }
+                match status {
+                    MIDIRendererStatus::Finished => {
+                        sender
+                            .send(RenderEvent::MIDIFinished { id })
+                            .unwrap_or_default();
+                        reported[id] = true;
+                    }
+                    MIDIRendererStatus::Error => {
+                        let error = errors[id]
+                            .clone()
+                            .unwrap_or_else(|| "Unknown error".to_owned());
+                        sender
+                            .send(RenderEvent::MIDIFailed { id, error })
+                            .unwrap_or_default();
+                        reported[id] = true;
+                    }
+                    _ => {}
+                }
+            }
+            *statuses.lock().unwrap() = current;
+
+            if finished {
+                info!("Render job finished");
+                mgr.cancel_all();
+                break;
+            }
+
+            drop(mgr);
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        sender.send(RenderEvent::Finished).unwrap_or_default();
+    }
+
+    /// Returns the progress of every MIDI in the job, `None` if it is not rendering yet
+    pub fn progress(&self) -> Vec<Option<RenderStats>> {
+        self.manager.lock().unwrap().get_stats()
+    }
+
+    pub fn statuses(&self) -> Vec<MIDIRendererStatus> {
+        self.statuses.lock().unwrap().clone()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn cancel_midi(&self, id: usize) {
+        self.manager.lock().unwrap().cancel(id);
+    }
+
+    /// Blocks until the job is done and returns the final status of every MIDI
+    pub fn wait(mut self) -> Vec<MIDIRendererStatus> {
+        if let Some(driver) = self.driver.take() {
+            driver.join().unwrap_or_default();
+        }
+        self.statuses()
+    }
+}
+
+impl Drop for RenderJob {
+    fn drop(&mut self) {
+        if self.driver.is_some() {
+            self.cancel();
+        } else {
+            self.manager.lock().unwrap().cancel_all();
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! The MIDI to audio pipeline used by Forte, without any of the user interface.
+//!
+//! A render is described by a [`settings::ForteConfig`], which has the same layout as the
+//! Forte config file, and is run with a [`job::RenderJob`].
+
+pub mod dsp;
+pub mod errors;
+pub mod job;
+pub mod midi;
+pub mod settings;
+pub mod writer;
+pub mod xsynth;
@@ -0,0 +1,456 @@
+//! Opening MIDI files. Files are recognized by their content rather than their
+//! extension, so both standard MIDI files and RIFF wrapped RMID files are accepted,
+//! as well as gzip, xz and zstd compressed versions of them.
+
+use midi_toolkit::io::{DiskReader, MIDIFile, MIDILoadError};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::info;
+
+/// The extensions MIDI files usually have, for file dialogs that can only filter by name
+pub const MIDI_EXTENSIONS: [&str; 5] = ["mid", "midi", "smf", "kar", "rmi"];
+
+/// The extensions of the compressed files, which follow the MIDI extension
+pub const COMPRESSED_EXTENSIONS: [&str; 3] = ["gz", "xz", "zst"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MIDIFormat {
+    /// A standard MIDI file, starting with the `MThd` chunk
+    Standard,
+    /// A RIFF file with a standard MIDI file in its `data` chunk
+    Rmid,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+        .unwrap_or(false)
+}
+
+/// Returns true if the path ends with one of the `MIDI_EXTENSIONS`, in any case,
+/// optionally followed by one of the `COMPRESSED_EXTENSIONS`
+pub fn has_midi_extension(path: &Path) -> bool {
+    if has_extension(path, &COMPRESSED_EXTENSIONS) {
+        has_extension(&path.with_extension(""), &MIDI_EXTENSIONS)
+    } else {
+        has_extension(path, &MIDI_EXTENSIONS)
+    }
+}
+
+// Reads the start of a file, which holds the magic numbers of every format
+fn read_header(reader: &mut impl Read) -> Option<[u8; 12]> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header).ok()?;
+    Some(header)
+}
+
+fn detect_compression(header: &[u8]) -> Option<Compression> {
+    match header {
+        [0x1F, 0x8B, ..] => Some(Compression::Gzip),
+        [0xFD, b'7', b'z', b'X', b'Z', 0x00, ..] => Some(Compression::Xz),
+        [0x28, 0xB5, 0x2F, 0xFD, ..] => Some(Compression::Zstd),
+        _ => None,
+    }
+}
+
+fn detect_format(header: &[u8; 12]) -> Option<MIDIFormat> {
+    match (&header[..4], &header[8..]) {
+        (b"MThd", _) => Some(MIDIFormat::Standard),
+        (b"RIFF", b"RMID") => Some(MIDIFormat::Rmid),
+        _ => None,
+    }
+}
+
+fn decoder(compression: Compression, file: File) -> io::Result<Box<dyn Read>> {
+    Ok(match compression {
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(file)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+    })
+}
+
+/// Finds the format of a MIDI file from its header, or `None` if it is not a MIDI.
+/// Compressed files are recognized by the header of their content.
+pub fn detect_midi_format(path: &Path) -> Option<MIDIFormat> {
+    let header = read_header(&mut File::open(path).ok()?)?;
+
+    match detect_compression(&header) {
+        Some(compression) => {
+            let mut decoder = decoder(compression, File::open(path).ok()?).ok()?;
+            detect_format(&read_header(&mut decoder)?)
+        }
+        None => detect_format(&header),
+    }
+}
+
+/// Returns true if the file starts with a MIDI or RMID header
+pub fn is_midi_file(path: &Path) -> bool {
+    detect_midi_format(path).is_some()
+}
+
+/// Opens a MIDI file of any supported format. Files that are not recognized are
+/// opened as standard MIDI files, so the parser can report what is wrong with them.
+pub fn open_midi(path: &Path) -> Result<MIDIFile<DiskReader>, MIDILoadError> {
+    MIDISource::new(path)?.open()
+}
+
+/// A MIDI file that can be opened any number of times. Compressed files are
+/// decompressed once to a temporary file, which is removed when the last clone
+/// and the last MIDI opened from it are dropped.
+#[derive(Clone, Debug)]
+pub struct MIDISource {
+    path: PathBuf,
+    size: u64,
+    decompressed: Option<Arc<TempPath>>,
+}
+
+impl MIDISource {
+    pub fn new(path: &Path) -> Result<Self, MIDILoadError> {
+        let mut file = File::open(path)?;
+        let compression = read_header(&mut file).and_then(|h| detect_compression(&h));
+        file.seek(SeekFrom::Start(0))?;
+
+        let (size, decompressed) = match compression {
+            Some(compression) => {
+                info!("Decompressing {:?} MIDI to a temporary file", compression);
+                let (mut temp, temp_path) = TempPath::create()?;
+                let size = io::copy(&mut decoder(compression, file)?, &mut temp)?;
+                (size, Some(Arc::new(temp_path)))
+            }
+            None => (file.metadata()?.len(), None),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            size,
+            decompressed,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The size of the MIDI after decompression
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn open(&self) -> Result<MIDIFile<DiskReader>, MIDILoadError> {
+        match &self.decompressed {
+            Some(temp) => open_stream(TempReader {
+                file: File::open(&temp.0)?,
+                _temp: temp.clone(),
+            }),
+            None => open_stream(File::open(&self.path)?),
+        }
+    }
+}
+
+fn open_stream<T: 'static + Read + Seek + Send>(
+    mut stream: T,
+) -> Result<MIDIFile<DiskReader>, MIDILoadError> {
+    let format = read_header(&mut stream).and_then(|h| detect_format(&h));
+    stream.seek(SeekFrom::Start(0))?;
+
+    match format {
+        Some(MIDIFormat::Rmid) => {
+            let (start, len) = find_rmid_data(&mut stream)?;
+            MIDIFile::open_from_stream(ChunkReader::new(stream, start, len)?, None)
+        }
+        Some(MIDIFormat::Standard) | None => MIDIFile::open_from_stream(stream, None),
+    }
+}
+
+// Returns the position and length of the `data` chunk in a RIFF RMID file
+fn find_rmid_data(file: &mut (impl Read + Seek)) -> Result<(u64, u64), MIDILoadError> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    // Skip the RIFF header and the RMID form type
+    let mut pos = 12;
+
+    while pos + 8 <= file_len {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header)?;
+
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        let start = pos + 8;
+        if &header[..4] == b"data" {
+            if start + len > file_len {
+                return Err(MIDILoadError::CorruptChunks);
+            }
+            return Ok((start, len));
+        }
+
+        // Chunks are padded to an even length
+        pos = start + len + (len & 1);
+    }
+
+    Err(MIDILoadError::CorruptChunks)
+}
+
+// Exposes one chunk of a file as if it was the whole file
+struct ChunkReader<T: Read + Seek> {
+    inner: T,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<T: Read + Seek> ChunkReader<T> {
+    fn new(mut inner: T, start: u64, len: u64) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            inner,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl<T: Read + Seek> Read for ChunkReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos) as usize;
+        let max = buf.len().min(remaining);
+        let read = self.inner.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Read + Seek> Seek for ChunkReader<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => self.len.checked_add_signed(p),
+            SeekFrom::Current(p) => self.pos.checked_add_signed(p),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+
+        self.inner.seek(SeekFrom::Start(self.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+// A file in the temp directory that is deleted once it is dropped
+#[derive(Debug)]
+struct TempPath(PathBuf);
+
+impl TempPath {
+    fn create() -> io::Result<(File, Self)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("forte-{}-{id}.mid", std::process::id()));
+
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        Ok((file, Self(path)))
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).unwrap_or_default();
+    }
+}
+
+// Keeps the temporary file while a MIDI reads from it. The file is closed before
+// the path is dropped, as it can't be removed while it is open on Windows.
+struct TempReader {
+    file: File,
+    _temp: Arc<TempPath>,
+}
+
+impl Read for TempReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Seek for TempReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    // A format 0 MIDI with one empty track at 96 PPQ
+    const SMF: &[u8] = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk\0\0\0\x04\0\xFF\x2F\0";
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn rmid(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        file.extend_from_slice(b"RMID");
+        file.extend(body);
+        file
+    }
+
+    fn format(bytes: &[u8]) -> Option<MIDIFormat> {
+        read_header(&mut Cursor::new(bytes)).and_then(|h| detect_format(&h))
+    }
+
+    fn data_chunk(file: &[u8]) -> Result<(u64, u64), MIDILoadError> {
+        find_rmid_data(&mut Cursor::new(file))
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(format(SMF), Some(MIDIFormat::Standard));
+        assert_eq!(
+            format(&rmid(&[chunk(b"data", SMF)])),
+            Some(MIDIFormat::Rmid)
+        );
+
+        // Other RIFF files, like WAV
+        let mut wave = rmid(&[chunk(b"data", &[0; 4])]);
+        wave[8..12].copy_from_slice(b"WAVE");
+        assert_eq!(format(&wave), None);
+        assert_eq!(format(b"not a midi file"), None);
+    }
+
+    #[test]
+    fn truncated_header() {
+        assert_eq!(format(b"RIFF\x04\0\0\0RM"), None);
+        assert_eq!(format(b"MThd"), None);
+        assert_eq!(format(b""), None);
+    }
+
+    #[test]
+    fn finds_data_after_padded_chunk() {
+        let file = rmid(&[chunk(b"INFO", b"odd"), chunk(b"data", SMF)]);
+        let (start, len) = data_chunk(&file).unwrap();
+        assert_eq!(&file[start as usize..(start + len) as usize], SMF);
+    }
+
+    #[test]
+    fn missing_data_chunk() {
+        let file = rmid(&[chunk(b"INFO", b"text")]);
+        assert!(matches!(
+            data_chunk(&file),
+            Err(MIDILoadError::CorruptChunks)
+        ));
+        assert!(matches!(
+            data_chunk(&rmid(&[])),
+            Err(MIDILoadError::CorruptChunks)
+        ));
+    }
+
+    #[test]
+    fn truncated_data_chunk() {
+        let mut file = rmid(&[chunk(b"data", SMF)]);
+        file.truncate(file.len() - 4);
+        assert!(matches!(
+            data_chunk(&file),
+            Err(MIDILoadError::CorruptChunks)
+        ));
+
+        // The chunk header itself is cut off
+        let mut file = rmid(&[]);
+        file.extend_from_slice(b"data\x10\0");
+        assert!(matches!(
+            data_chunk(&file),
+            Err(MIDILoadError::CorruptChunks)
+        ));
+    }
+
+    #[test]
+    fn chunk_reader_stays_in_chunk() {
+        let file = rmid(&[chunk(b"data", SMF), chunk(b"LIST", b"more")]);
+        let (start, len) = data_chunk(&file).unwrap();
+        let mut reader = ChunkReader::new(Cursor::new(file), start, len).unwrap();
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, SMF);
+
+        assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), len - 4);
+        let mut end = Vec::new();
+        reader.read_to_end(&mut end).unwrap();
+        assert_eq!(end, &SMF[SMF.len() - 4..]);
+
+        assert!(reader.seek(SeekFrom::Current(-100)).is_err());
+    }
+
+    #[test]
+    fn opens_rmid() {
+        let midi = open_stream(Cursor::new(rmid(&[chunk(b"data", SMF)]))).unwrap();
+        assert_eq!(midi.track_count(), 1);
+        assert_eq!(midi.ppq(), 96);
+    }
+
+    #[test]
+    fn midi_extensions() {
+        for path in [
+            "song.mid",
+            "song.MIDI",
+            "song.rmi",
+            "song.mid.gz",
+            "song.kar.zst",
+        ] {
+            assert!(has_midi_extension(Path::new(path)), "{path}");
+        }
+        for path in ["song.wav", "song.gz", "song.mid.zip", "song"] {
+            assert!(!has_midi_extension(Path::new(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn compressed_midi_is_decompressed_once() {
+        let path = std::env::temp_dir().join(format!("forte-gz-{}.mid.gz", std::process::id()));
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(SMF).unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(detect_midi_format(&path), Some(MIDIFormat::Standard));
+        let source = MIDISource::new(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(source.size(), SMF.len() as u64);
+
+        // The source file is gone, so both opens read the decompressed copy
+        let temp = source.decompressed.as_ref().unwrap().0.clone();
+        let midi = source.clone().open().unwrap();
+        assert_eq!(source.open().unwrap().ppq(), 96);
+
+        drop(source);
+        assert!(temp.exists());
+        // The MIDI reader drops the file on its own thread
+        drop(midi);
+        for _ in 0..100 {
+            if !temp.exists() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(!temp.exists());
+    }
+}
@@ -0,0 +1,676 @@
+use crate::dsp::{DSPEffect, DSPSettings};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::prelude::*;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use xsynth_core::channel::ChannelInitOptions;
+use xsynth_core::soundfont::{Interpolator, SoundfontInitOptions};
+use xsynth_core::ChannelCount;
+
+#[derive(PartialEq, Eq, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum SynthCfgType {
+    #[default]
+    Global,
+    PerChannel,
+}
+
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SFFormat {
+    #[default]
+    Sfz,
+    Sf2,
+}
+
+#[derive(Clone, PartialEq, Eq, Copy, Debug, Serialize, Deserialize)]
+#[serde(remote = "Interpolator")]
+pub enum InterpolatorDef {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(remote = "SoundfontInitOptions")]
+pub struct SoundfontInitOptionsDef {
+    pub bank: Option<u8>,
+    pub preset: Option<u8>,
+    pub linear_release: bool,
+    pub use_effects: bool,
+    #[serde(with = "InterpolatorDef")]
+    pub interpolator: Interpolator,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForteSFListItem {
+    pub id: usize,
+    pub enabled: bool,
+    pub selected: bool,
+    pub format: SFFormat,
+    pub path: PathBuf,
+    #[serde(with = "SoundfontInitOptionsDef")]
+    pub init: SoundfontInitOptions,
+}
+
+/// Identifies a loaded soundfont. The same SF2 file can be used with a different
+/// bank and preset on every channel, so the path alone is not enough.
+pub type SoundfontKey = (PathBuf, Option<u8>, Option<u8>);
+
+impl ForteSFListItem {
+    pub fn key(&self) -> SoundfontKey {
+        (self.path.clone(), self.init.bank, self.init.preset)
+    }
+}
+
+#[derive(Default, Copy, Clone, Serialize, Deserialize)]
+pub enum RenderMode {
+    #[default]
+    Standard = 0,
+    RealtimeSimulation = 1,
+}
+
+impl From<RenderMode> for usize {
+    fn from(val: RenderMode) -> Self {
+        val as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(remote = "ChannelInitOptions", default)]
+pub struct ChannelInitOptionsDef {
+    pub fade_out_killing: bool,
+    pub drums_only: bool,
+}
+
+impl Default for ChannelInitOptionsDef {
+    fn default() -> Self {
+        Self {
+            fade_out_killing: true,
+            drums_only: false,
+        }
+    }
+}
+
+/// Applied to the output of a channel before it is summed with the other channels
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelMixerSettings {
+    pub gain_db: f32,
+    /// From -1.0 (left) to 1.0 (right)
+    pub pan: f32,
+    pub mute: bool,
+    pub solo: bool,
+    pub effects: Vec<DSPEffect>,
+}
+
+impl Default for ChannelMixerSettings {
+    fn default() -> Self {
+        Self {
+            gain_db: 0.0,
+            pan: 0.0,
+            mute: false,
+            solo: false,
+            effects: Vec::new(),
+        }
+    }
+}
+
+/// Maps the velocity of every NoteOn to a new one
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum VelocityCurve {
+    #[default]
+    Unchanged,
+    /// `velocity * scale + offset`
+    Linear { scale: f32, offset: f32 },
+    /// Exponents above 1.0 make quiet notes quieter, below 1.0 louder
+    Exponential { exponent: f32 },
+    /// The new velocity of every velocity from 0 to 127
+    Table { table: Vec<u8> },
+}
+
+impl VelocityCurve {
+    /// Every curve with its default parameters, in the order they are offered in the UI
+    pub fn all() -> [VelocityCurve; 4] {
+        [
+            VelocityCurve::Unchanged,
+            VelocityCurve::Linear {
+                scale: 1.0,
+                offset: 0.0,
+            },
+            VelocityCurve::Exponential { exponent: 1.0 },
+            VelocityCurve::Table {
+                table: (0..128).collect(),
+            },
+        ]
+    }
+
+    /// Returns the new velocity of every velocity. Velocity 0 is a note off and stays
+    /// 0, while notes never end up at velocity 0, which would turn them into note offs.
+    pub fn to_table(&self) -> [u8; 128] {
+        let mut table = [0; 128];
+        for (vel, out) in table.iter_mut().enumerate().skip(1) {
+            let new = match self {
+                VelocityCurve::Unchanged => vel as f32,
+                VelocityCurve::Linear { scale, offset } => vel as f32 * scale + offset,
+                VelocityCurve::Exponential { exponent } => {
+                    (vel as f32 / 127.0).powf(*exponent) * 127.0
+                }
+                VelocityCurve::Table { table } => {
+                    table.get(vel).copied().unwrap_or(vel as u8) as f32
+                }
+            };
+            *out = new.round().clamp(1.0, 127.0) as u8;
+        }
+        table
+    }
+}
+
+impl std::fmt::Display for VelocityCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VelocityCurve::Unchanged => write!(f, "Unchanged"),
+            VelocityCurve::Linear { .. } => write!(f, "Linear"),
+            VelocityCurve::Exponential { .. } => write!(f, "Exponential"),
+            VelocityCurve::Table { .. } => write!(f, "Lookup Table"),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SingleChannelSettings {
+    #[serde(with = "ChannelInitOptionsDef")]
+    pub channel_init_options: ChannelInitOptions,
+    pub layer_limit: usize,
+    pub layer_limit_enabled: bool,
+    pub soundfonts: Vec<ForteSFListItem>,
+    pub use_threadpool: bool,
+    pub mixer: ChannelMixerSettings,
+    /// XSynth has no aftertouch input, so channel pressure is sent as this CC instead
+    pub aftertouch_cc: Option<u8>,
+    /// Also send polyphonic key pressure to `aftertouch_cc`. The synth can only
+    /// modulate whole channels, so the last pressure of any key is used.
+    pub map_poly_aftertouch: bool,
+    /// The pitch bend range in semitones, until the MIDI sets it through RPN 0
+    pub pitch_bend_range: f32,
+    pub velocity_curve: VelocityCurve,
+    /// Notes outside of this range are removed
+    pub key_range: RangeInclusive<u8>,
+}
+
+impl Default for SingleChannelSettings {
+    fn default() -> Self {
+        let channel_init_options = ChannelInitOptions {
+            fade_out_killing: true,
+            drums_only: false,
+        };
+
+        Self {
+            channel_init_options,
+            layer_limit: 16,
+            layer_limit_enabled: true,
+            soundfonts: Vec::new(),
+            use_threadpool: true,
+            mixer: Default::default(),
+            aftertouch_cc: None,
+            map_poly_aftertouch: false,
+            pitch_bend_range: 2.0,
+            velocity_curve: Default::default(),
+            key_range: 0..=127,
+        }
+    }
+}
+
+/// The number of channels addressed by one MIDI port
+pub const CHANNELS_PER_PORT: usize = 16;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SynthSettings {
+    pub sfcfg_type: SynthCfgType,
+    pub chcfg_type: SynthCfgType,
+    pub global_settings: SingleChannelSettings,
+    /// 16 channels for every configured port, in port order
+    pub individual_settings: Vec<SingleChannelSettings>,
+}
+
+impl Default for SynthSettings {
+    fn default() -> Self {
+        Self {
+            sfcfg_type: SynthCfgType::Global,
+            chcfg_type: SynthCfgType::Global,
+            global_settings: Default::default(),
+            individual_settings: vec![Default::default(); 16],
+        }
+    }
+}
+
+impl SynthSettings {
+    /// The number of MIDI ports that have their own per-channel settings
+    pub fn configured_ports(&self) -> usize {
+        self.individual_settings
+            .len()
+            .div_ceil(CHANNELS_PER_PORT)
+            .max(1)
+    }
+
+    /// Returns the settings of one channel. Channels on ports without their own settings
+    /// use the same channel of the first port, or the defaults if that is missing too.
+    pub fn individual(&self, idx: usize) -> SingleChannelSettings {
+        self.individual_settings
+            .get(idx)
+            .or_else(|| self.individual_settings.get(idx % CHANNELS_PER_PORT))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the settings of every channel on the given number of MIDI ports
+    pub fn unify(&self, ports: usize) -> Vec<SingleChannelSettings> {
+        let mut vec = vec![SingleChannelSettings::default(); ports * CHANNELS_PER_PORT];
+
+        // Save the channel settings first because the config type might be different
+        // for the soundfonts and it may override the first values
+
+        match self.chcfg_type {
+            SynthCfgType::Global => {
+                for c in vec.iter_mut() {
+                    *c = self.global_settings.clone();
+                }
+            }
+            SynthCfgType::PerChannel => {
+                for (idx, c) in vec.iter_mut().enumerate() {
+                    *c = self.individual(idx);
+                }
+            }
+        }
+
+        match self.sfcfg_type {
+            SynthCfgType::Global => {
+                for c in vec.iter_mut() {
+                    c.soundfonts = self.global_settings.soundfonts.clone();
+                }
+            }
+            SynthCfgType::PerChannel => {
+                for (idx, c) in vec.iter_mut().enumerate() {
+                    c.soundfonts = self.individual(idx).soundfonts;
+                }
+            }
+        }
+
+        vec
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "ChannelCount")]
+pub enum ChannelCountDef {
+    Mono,
+    Stereo,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum PCMSampleFormat {
+    Int16,
+    Int24,
+    Float32,
+    Float64,
+}
+
+impl std::fmt::Display for PCMSampleFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PCMSampleFormat::Int16 => write!(f, "16-bit integer"),
+            PCMSampleFormat::Int24 => write!(f, "24-bit integer"),
+            PCMSampleFormat::Float32 => write!(f, "32-bit float"),
+            PCMSampleFormat::Float64 => write!(f, "64-bit float"),
+        }
+    }
+}
+
+impl PCMSampleFormat {
+    pub fn is_integer(&self) -> bool {
+        matches!(self, PCMSampleFormat::Int16 | PCMSampleFormat::Int24)
+    }
+}
+
+/// Dither applied when converting to an integer sample format
+#[derive(Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum DitherMode {
+    None,
+    #[default]
+    Tpdf,
+    NoiseShaped,
+}
+
+impl std::fmt::Display for DitherMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DitherMode::None => write!(f, "None"),
+            DitherMode::Tpdf => write!(f, "TPDF"),
+            DitherMode::NoiseShaped => write!(f, "Noise Shaped"),
+        }
+    }
+}
+
+/// `Auto` writes plain WAV and switches to RF64 for files over the 4GB RIFF limit
+#[derive(Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum WavContainer {
+    #[default]
+    Auto,
+    Wave64,
+}
+
+impl std::fmt::Display for WavContainer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WavContainer::Auto => write!(f, "WAV / RF64"),
+            WavContainer::Wave64 => write!(f, "Sony Wave64"),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "args")]
+pub enum OutputAudioFormat {
+    Pcm {
+        format: PCMSampleFormat,
+        #[serde(default)]
+        dither: DitherMode,
+        #[serde(default)]
+        container: WavContainer,
+    },
+    Vorbis {
+        bitrate: u32,
+    },
+    Lame {
+        bitrate: u32,
+    },
+    Flac {
+        compression_level: u8,
+        #[serde(default)]
+        dither: DitherMode,
+    },
+    Opus {
+        bitrate: u32,
+        complexity: u8,
+    },
+}
+
+impl std::fmt::Display for OutputAudioFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OutputAudioFormat::Pcm { .. } => write!(f, "WAV"),
+            OutputAudioFormat::Vorbis { .. } => write!(f, "OGG"),
+            OutputAudioFormat::Lame { .. } => write!(f, "MP3"),
+            OutputAudioFormat::Flac { .. } => write!(f, "FLAC"),
+            OutputAudioFormat::Opus { .. } => write!(f, "OPUS"),
+        }
+    }
+}
+
+#[derive(Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StemMode {
+    #[default]
+    Disabled,
+    PerChannel,
+    PerTrack,
+}
+
+impl std::fmt::Display for StemMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StemMode::Disabled => write!(f, "Disabled"),
+            StemMode::PerChannel => write!(f, "Per Channel"),
+            StemMode::PerTrack => write!(f, "Per Track"),
+        }
+    }
+}
+
+#[derive(Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum RenderRangeUnit {
+    #[default]
+    Seconds,
+    Ticks,
+}
+
+impl std::fmt::Display for RenderRangeUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RenderRangeUnit::Seconds => write!(f, "Seconds"),
+            RenderRangeUnit::Ticks => write!(f, "Ticks"),
+        }
+    }
+}
+
+/// What happens to notes that the transpose moves outside of the MIDI key range
+#[derive(Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum TransposeMode {
+    #[default]
+    Drop,
+    Clamp,
+}
+
+impl std::fmt::Display for TransposeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TransposeMode::Drop => write!(f, "Drop"),
+            TransposeMode::Clamp => write!(f, "Clamp"),
+        }
+    }
+}
+
+/// The part of the MIDI that is rendered. Without an `end`, the MIDI is rendered
+/// until its last event.
+#[derive(Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderRange {
+    pub enabled: bool,
+    pub unit: RenderRangeUnit,
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+#[derive(Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoteDropMode {
+    #[default]
+    Disabled,
+    EventsPerBuffer,
+    MaxNps,
+}
+
+impl std::fmt::Display for NoteDropMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NoteDropMode::Disabled => write!(f, "Disabled"),
+            NoteDropMode::EventsPerBuffer => write!(f, "Events per Buffer"),
+            NoteDropMode::MaxNps => write!(f, "Max NPS"),
+        }
+    }
+}
+
+/// Skips note ons in the realtime simulation when the MIDI sends more than a live
+/// player could handle, which is how players like OmniMIDI keep up with black MIDIs
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteDropSettings {
+    pub mode: NoteDropMode,
+    /// The events one buffer of `realtime_buffer_ms` can take before notes are skipped
+    pub events_per_buffer: u32,
+    /// The most notes played in any second
+    pub max_nps: u32,
+}
+
+impl Default for NoteDropSettings {
+    fn default() -> Self {
+        Self {
+            mode: NoteDropMode::Disabled,
+            events_per_buffer: 10000,
+            max_nps: 500000,
+        }
+    }
+}
+
+/// Filters that remove notes before they reach the synth, together with their note offs
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteFilterSettings {
+    /// Removes notes shorter than this many ticks
+    pub min_length: Option<u32>,
+    /// Removes notes that start while the same key is held on the same channel
+    pub remove_overlaps: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub sample_rate: u32,
+    #[serde(with = "ChannelCountDef")]
+    pub audio_channels: ChannelCount,
+    pub dsp_settings: DSPSettings,
+    pub render_mode: RenderMode,
+    pub vel_ignore_range: RangeInclusive<u8>,
+    pub realtime_buffer_ms: f32,
+    pub parallel_midis: usize,
+    pub output_dir: Option<PathBuf>,
+    pub audio_format: OutputAudioFormat,
+    pub stem_mode: StemMode,
+    pub stems_include_mix: bool,
+    /// Groups of track numbers (starting from 1) that are rendered to one stem in
+    /// per-track mode. Tracks outside of every group share an extra stem. If empty,
+    /// every track gets its own stem.
+    pub stem_track_groups: Vec<Vec<usize>>,
+    pub render_range: RenderRange,
+    /// Playback speed multiplier, 2.0 renders the MIDI twice as fast
+    pub speed: f64,
+    pub transpose: i8,
+    pub transpose_mode: TransposeMode,
+    pub note_filter: NoteFilterSettings,
+    /// Only used in the realtime simulation mode
+    pub note_drop: NoteDropSettings,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            audio_channels: ChannelCount::Stereo,
+            dsp_settings: Default::default(),
+            render_mode: RenderMode::Standard,
+            vel_ignore_range: 0..=0,
+            realtime_buffer_ms: 100.0 / 6.0,
+            output_dir: None,
+            parallel_midis: 1,
+            audio_format: OutputAudioFormat::Pcm {
+                format: PCMSampleFormat::Float32,
+                dither: DitherMode::Tpdf,
+                container: WavContainer::Auto,
+            },
+            stem_mode: StemMode::Disabled,
+            stems_include_mix: true,
+            stem_track_groups: Vec::new(),
+            render_range: Default::default(),
+            speed: 1.0,
+            transpose: 0,
+            transpose_mode: TransposeMode::Drop,
+            note_filter: Default::default(),
+            note_drop: Default::default(),
+        }
+    }
+}
+
+/// Everything needed to render a list of MIDIs. This is the part of the Forte
+/// config file that is not related to the user interface.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForteConfig {
+    pub synth_settings: SynthSettings,
+    pub render_settings: RenderSettings,
+}
+
+impl ForteConfig {
+    pub fn load_from(path: &Path) -> std::io::Result<ForteConfig> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        toml::from_str(&contents)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unify_with_missing_channels() {
+        let mut settings = SynthSettings {
+            chcfg_type: SynthCfgType::PerChannel,
+            sfcfg_type: SynthCfgType::PerChannel,
+            ..Default::default()
+        };
+        settings.individual_settings.truncate(3);
+        settings.individual_settings[1].layer_limit = 7;
+
+        assert_eq!(settings.configured_ports(), 1);
+        let channels = settings.unify(2);
+        assert_eq!(channels.len(), 2 * CHANNELS_PER_PORT);
+        assert_eq!(channels[1].layer_limit, 7);
+        assert_eq!(channels[17].layer_limit, 7);
+        assert_eq!(
+            channels[5].layer_limit,
+            SingleChannelSettings::default().layer_limit
+        );
+
+        settings.individual_settings.clear();
+        assert_eq!(settings.unify(1).len(), CHANNELS_PER_PORT);
+    }
+
+    #[test]
+    fn unchanged_velocity_curve_is_identity() {
+        let table = VelocityCurve::Unchanged.to_table();
+        for (vel, out) in table.iter().enumerate() {
+            assert_eq!(*out as usize, vel);
+        }
+        assert_eq!(
+            VelocityCurve::Exponential { exponent: 1.0 }.to_table(),
+            table
+        );
+        assert_eq!(
+            VelocityCurve::Linear {
+                scale: 1.0,
+                offset: 0.0
+            }
+            .to_table(),
+            table
+        );
+    }
+
+    #[test]
+    fn velocity_curves_keep_notes_on() {
+        let silent = VelocityCurve::Linear {
+            scale: 0.0,
+            offset: -10.0,
+        }
+        .to_table();
+        assert_eq!(silent[0], 0);
+        assert!(silent[1..].iter().all(|&vel| vel == 1));
+
+        let loud = VelocityCurve::Linear {
+            scale: 2.0,
+            offset: 0.0,
+        }
+        .to_table();
+        assert_eq!(loud[0], 0);
+        assert_eq!(loud[10], 20);
+        assert_eq!(loud[127], 127);
+
+        let table = VelocityCurve::Table {
+            table: vec![0; 128],
+        }
+        .to_table();
+        assert_eq!(table[0], 0);
+        assert!(table[1..].iter().all(|&vel| vel == 1));
+    }
+}
@@ -0,0 +1,498 @@
+use crate::dsp::{db_to_gain, ForteAudioDSP};
+use crate::errors::error_types::MIDIRendererError;
+use crate::settings::{ForteConfig, OutputAudioFormat, StemMode, WavContainer};
+use std::path::PathBuf;
+use tracing::info;
+
+mod dither;
+pub mod flac;
+pub mod lame;
+pub mod opus;
+mod pcm;
+mod spool;
+pub mod vorbis;
+
+pub const COMMON_SAMPLE_RATES: [u32; 12] = [
+    8_000, 11_025, 16_000, 22_050, 44_100, 48_000, 82_200, 96_000, 176_400, 192_000, 352_800,
+    384_000,
+];
+// Added to the MIDI length when estimating the output size, for the release tails
+const ESTIMATE_TAIL_SECS: f64 = 10.0;
+
+pub const COMMON_BITRATES: [u32; 8] = [
+    64_000, 80_000, 96_000, 128_000, 160_000, 192_000, 256_000, 320_000,
+];
+
+pub trait AudioWriter {
+    fn write_samples(&mut self, samples: Vec<f32>) -> Result<(), MIDIRendererError>;
+    fn finalize(self: Box<Self>) -> Result<(), MIDIRendererError>;
+}
+
+pub struct ForteAudioFileWriter {
+    // None when the output is only analyzed for its normalization gain
+    writer: Option<Box<dyn AudioWriter>>,
+    // None for stems, which skip the master chain
+    dsp: Option<ForteAudioDSP>,
+    spool: Option<spool::SampleSpool>,
+    chunk_len: usize,
+    peak: f32,
+}
+
+fn create_audio_writer(
+    config: &ForteConfig,
+    filename: String,
+    length: Option<f64>,
+) -> Result<(Box<dyn AudioWriter>, PathBuf), MIDIRendererError> {
+    let extension = match config.render_settings.audio_format {
+        OutputAudioFormat::Pcm {
+            container: WavContainer::Wave64,
+            ..
+        } => "w64",
+        OutputAudioFormat::Pcm { .. } => "wav",
+        OutputAudioFormat::Vorbis { .. } => "ogg",
+        OutputAudioFormat::Lame { .. } => "mp3",
+        OutputAudioFormat::Flac { .. } => "flac",
+        OutputAudioFormat::Opus { .. } => "opus",
+    };
+
+    let filepath = match config.render_settings.output_dir.clone() {
+        Some(mut dir) => {
+            dir.push(filename);
+            dir.set_extension(extension);
+
+            let mut counter = 1;
+            let mut filepath_new = dir.clone();
+            while filepath_new.exists() {
+                let filename = dir.file_name().unwrap().to_str().unwrap();
+                let len = filename.len() - extension.len() - 1;
+                filepath_new = dir.with_file_name(
+                    filename[0..len].to_string() + format!(" ({counter}).").as_str() + extension,
+                );
+                counter += 1;
+            }
+            filepath_new
+        }
+        None => filename.into(),
+    };
+
+    let sample_rate = config.render_settings.sample_rate;
+    let channels = config.render_settings.audio_channels.count();
+    let estimated_frames =
+        length.map(|length| ((length + ESTIMATE_TAIL_SECS) * sample_rate as f64) as u64);
+
+    let mut spool_path = filepath.clone().into_os_string();
+    spool_path.push(".tmp");
+
+    let writer: Box<dyn AudioWriter> = match config.render_settings.audio_format {
+        OutputAudioFormat::Pcm {
+            format,
+            dither,
+            container,
+        } => Box::new(pcm::PCMFileWriter::new(
+            channels,
+            sample_rate,
+            format,
+            dither,
+            container,
+            estimated_frames,
+            filepath,
+        )?),
+        OutputAudioFormat::Vorbis { bitrate } => Box::new(vorbis::VorbisFileWriter::new(
+            channels,
+            sample_rate,
+            bitrate,
+            filepath,
+        )?),
+        OutputAudioFormat::Lame { bitrate } => Box::new(lame::LameFileWriter::new(
+            channels,
+            sample_rate,
+            bitrate,
+            filepath,
+        )?),
+        OutputAudioFormat::Flac {
+            compression_level,
+            dither,
+        } => Box::new(flac::FlacFileWriter::new(
+            channels,
+            sample_rate,
+            compression_level,
+            dither,
+            filepath,
+        )?),
+        OutputAudioFormat::Opus {
+            bitrate,
+            complexity,
+        } => Box::new(opus::OpusFileWriter::new(
+            channels,
+            sample_rate,
+            bitrate,
+            complexity,
+            filepath,
+        )?),
+    };
+
+    Ok((writer, spool_path.into()))
+}
+
+impl ForteAudioFileWriter {
+    /// Writes the full mix through the master chain.
+    /// `length` is the length of the MIDI in seconds, if it is known
+    pub fn new(
+        config: &ForteConfig,
+        filename: String,
+        length: Option<f64>,
+    ) -> Result<Self, MIDIRendererError> {
+        let (writer, spool_path) = create_audio_writer(config, filename, length)?;
+        let dsp = Self::master_chain(config);
+
+        let spool = if dsp.is_two_pass() {
+            Some(spool::SampleSpool::new(spool_path)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            writer: Some(writer),
+            dsp: Some(dsp),
+            spool,
+            chunk_len: Self::chunk_len(config),
+            peak: 0.0,
+        })
+    }
+
+    /// Writes a stem without the master chain. A two pass render keeps the
+    /// samples until the normalization gain of the mix is known.
+    pub fn new_stem(
+        config: &ForteConfig,
+        filename: String,
+        length: Option<f64>,
+    ) -> Result<Self, MIDIRendererError> {
+        let (writer, spool_path) = create_audio_writer(config, filename, length)?;
+
+        let spool = if config.render_settings.dsp_settings.is_two_pass() {
+            Some(spool::SampleSpool::new(spool_path)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            writer: Some(writer),
+            dsp: None,
+            spool,
+            chunk_len: Self::chunk_len(config),
+            peak: 0.0,
+        })
+    }
+
+    /// Runs the master chain without writing a file, to get the normalization gain of the mix
+    pub fn analyzer(config: &ForteConfig) -> Self {
+        Self {
+            writer: None,
+            dsp: Some(Self::master_chain(config)),
+            spool: None,
+            chunk_len: Self::chunk_len(config),
+            peak: 0.0,
+        }
+    }
+
+    fn master_chain(config: &ForteConfig) -> ForteAudioDSP {
+        ForteAudioDSP::new(
+            config.render_settings.audio_channels.count(),
+            config.render_settings.sample_rate,
+            config.render_settings.dsp_settings.clone(),
+        )
+    }
+
+    fn chunk_len(config: &ForteConfig) -> usize {
+        config.render_settings.sample_rate as usize
+            * config.render_settings.audio_channels.count() as usize
+    }
+
+    pub fn write_samples(&mut self, mut samples: Vec<f32>) -> Result<(), MIDIRendererError> {
+        if let Some(dsp) = self.dsp.as_mut() {
+            dsp.process(&mut samples);
+        }
+        self.write_processed(samples)
+    }
+
+    fn write_processed(&mut self, samples: Vec<f32>) -> Result<(), MIDIRendererError> {
+        self.peak = samples.iter().fold(self.peak, |peak, s| peak.max(s.abs()));
+
+        match (self.spool.as_mut(), self.writer.as_mut()) {
+            (Some(spool), _) => spool.write(&samples),
+            (None, Some(writer)) => writer.write_samples(samples),
+            (None, None) => Ok(()),
+        }
+    }
+
+    // Writes what is left in the master chain
+    fn flush(&mut self) -> Result<(), MIDIRendererError> {
+        if let Some(dsp) = self.dsp.as_mut() {
+            let tail = dsp.flush();
+            self.write_processed(tail)?;
+        }
+        Ok(())
+    }
+
+    // The gain of the second pass, once every sample went through the master chain
+    fn normalization_gain(&self) -> f32 {
+        let dsp = match self.dsp.as_ref() {
+            Some(dsp) => dsp,
+            None => return 1.0,
+        };
+        let mut gain = dsp.normalization_gain();
+
+        let settings = dsp.settings();
+        if settings.peak_normalization.enabled && self.peak > 0.0 {
+            let target = db_to_gain(settings.peak_normalization.target_db) / self.peak;
+            // With loudness normalization the peak target only works as a ceiling
+            gain = if settings.loudness.enabled {
+                gain.min(target)
+            } else {
+                target
+            };
+        }
+
+        gain
+    }
+
+    fn finish(mut self, gain: f32) -> Result<(), MIDIRendererError> {
+        if let (Some(spool), Some(writer)) = (self.spool.take(), self.writer.as_mut()) {
+            info!("Applying a normalization gain of {:.2}", gain);
+
+            spool.replay(self.chunk_len, |mut samples| {
+                for s in samples.iter_mut() {
+                    *s *= gain;
+                }
+                writer.write_samples(samples)
+            })?;
+        }
+
+        match self.writer {
+            Some(writer) => writer.finalize(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn finalize(mut self) -> Result<(), MIDIRendererError> {
+        self.flush()?;
+        let gain = self.normalization_gain();
+        self.finish(gain)
+    }
+}
+
+/// A block of rendered audio. `stems` holds one buffer per stem and is empty
+/// when stem export is disabled.
+pub struct RenderedBlock {
+    pub mix: Vec<f32>,
+    pub stems: Vec<Vec<f32>>,
+}
+
+/// Writes the full mix and, if enabled, every stem of a render to its own file.
+/// Stems skip the master chain and get the normalization gain of the mix, so
+/// they keep the balance they have in the mix.
+pub struct ForteOutputWriter {
+    // Also there without a mix file if the stems need its normalization gain
+    mix: Option<ForteAudioFileWriter>,
+    stems: Vec<ForteAudioFileWriter>,
+}
+
+impl ForteOutputWriter {
+    pub fn new(
+        config: &ForteConfig,
+        filename: String,
+        stem_names: &[String],
+        length: Option<f64>,
+    ) -> Result<Self, MIDIRendererError> {
+        let stems_enabled = config.render_settings.stem_mode != StemMode::Disabled;
+
+        let mix = if !stems_enabled || config.render_settings.stems_include_mix {
+            Some(ForteAudioFileWriter::new(config, filename.clone(), length)?)
+        } else if config.render_settings.dsp_settings.is_two_pass() {
+            Some(ForteAudioFileWriter::analyzer(config))
+        } else {
+            None
+        };
+
+        let mut stems = Vec::new();
+        if stems_enabled {
+            info!("Creating {} stem writers", stem_names.len());
+            for name in stem_names {
+                let stem_filename = suffixed_filename(&filename, &format!("_{name}"));
+                stems.push(ForteAudioFileWriter::new_stem(
+                    config,
+                    stem_filename,
+                    length,
+                )?);
+            }
+        }
+
+        Ok(Self { mix, stems })
+    }
+
+    pub fn write_block(&mut self, block: RenderedBlock) -> Result<(), MIDIRendererError> {
+        if let Some(mix) = self.mix.as_mut() {
+            mix.write_samples(block.mix)?;
+        }
+        for (writer, samples) in self.stems.iter_mut().zip(block.stems) {
+            writer.write_samples(samples)?;
+        }
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> Result<(), MIDIRendererError> {
+        let gain = match self.mix.as_mut() {
+            Some(mix) => {
+                mix.flush()?;
+                mix.normalization_gain()
+            }
+            None => 1.0,
+        };
+
+        if let Some(mix) = self.mix {
+            mix.finish(gain)?;
+        }
+        for writer in self.stems {
+            writer.finish(gain)?;
+        }
+        Ok(())
+    }
+}
+
+// Inserts the suffix before the extension, so "song.mid" becomes "song_ch01.mid"
+// and the output extension can still be swapped in later
+fn suffixed_filename(filename: &str, suffix: &str) -> String {
+    match filename.rsplit_once('.') {
+        Some((base, ext)) => format!("{base}{suffix}.{ext}"),
+        None => format!("{filename}{suffix}"),
+    }
+}
+
+pub fn split_stereo(vec: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    let left_sgnl = vec
+        .iter()
+        .enumerate()
+        .filter(|&i| i.0 % 2 == 0)
+        .map(|i| *i.1)
+        .collect::<Vec<_>>();
+    let right_sgnl = vec
+        .iter()
+        .enumerate()
+        .filter(|&i| i.0 % 2 != 0)
+        .map(|i| *i.1)
+        .collect::<Vec<_>>();
+    (left_sgnl, right_sgnl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{DitherMode, PCMSampleFormat};
+    use std::path::Path;
+
+    // Reads the samples of a 32-bit float WAV file
+    fn read_wav(path: &Path) -> Vec<f32> {
+        let bytes = std::fs::read(path).unwrap();
+        let data = bytes.windows(4).position(|w| w == b"data").unwrap() + 8;
+        bytes[data..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    fn config(dir: &Path, include_mix: bool) -> ForteConfig {
+        let mut config = ForteConfig::default();
+        let settings = &mut config.render_settings;
+        settings.output_dir = Some(dir.to_path_buf());
+        settings.audio_format = OutputAudioFormat::Pcm {
+            format: PCMSampleFormat::Float32,
+            dither: DitherMode::None,
+            container: WavContainer::Auto,
+        };
+        settings.stem_mode = StemMode::PerChannel;
+        settings.stems_include_mix = include_mix;
+        settings.dsp_settings.limiter.enabled = false;
+        settings.dsp_settings.peak_normalization.enabled = true;
+        settings.dsp_settings.peak_normalization.target_db = -6.0;
+        config
+    }
+
+    // A loud and a quiet stem
+    fn write_stems(config: &ForteConfig) -> Vec<f32> {
+        let names = ["loud".to_owned(), "quiet".to_owned()];
+        let mut writer =
+            ForteOutputWriter::new(config, "song.mid".to_owned(), &names, None).unwrap();
+
+        let mut mix = Vec::new();
+        for block in 0..4 {
+            let loud: Vec<f32> = (0..2000)
+                .map(|i| ((block * 2000 + i) as f32 * 0.01).sin() * 0.2)
+                .collect();
+            let quiet: Vec<f32> = (0..2000)
+                .map(|i| ((block * 2000 + i) as f32 * 0.03).cos() * 0.01)
+                .collect();
+            let block_mix: Vec<f32> = loud.iter().zip(&quiet).map(|(a, b)| a + b).collect();
+            mix.extend_from_slice(&block_mix);
+
+            writer
+                .write_block(RenderedBlock {
+                    mix: block_mix,
+                    stems: vec![loud, quiet],
+                })
+                .unwrap();
+        }
+        writer.finalize().unwrap();
+        mix
+    }
+
+    fn sum(stems: &[Vec<f32>]) -> Vec<f32> {
+        (0..stems[0].len())
+            .map(|i| stems.iter().map(|s| s[i]).sum())
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn stems_add_up_to_the_mix() {
+        let dir = std::env::temp_dir().join(format!("forte-stems-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_stems(&config(&dir, true));
+
+        let mix = read_wav(&dir.join("song.wav"));
+        let stems = [
+            read_wav(&dir.join("song_loud.wav")),
+            read_wav(&dir.join("song_quiet.wav")),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!((peak(&mix) - db_to_gain(-6.0)).abs() < 1e-4);
+        assert!(peak(&stems[1]) < 0.1);
+        assert_eq!(mix.len(), stems[0].len());
+        for (a, b) in sum(&stems).iter().zip(&mix) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn stems_get_the_mix_gain_without_a_mix_file() {
+        let dir = std::env::temp_dir().join(format!("forte-stems-only-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mix = write_stems(&config(&dir, false));
+
+        let has_mix = dir.join("song.wav").exists();
+        let stems = [
+            read_wav(&dir.join("song_loud.wav")),
+            read_wav(&dir.join("song_quiet.wav")),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!has_mix);
+        let gain = db_to_gain(-6.0) / peak(&mix);
+        for (a, b) in sum(&stems).iter().zip(&mix) {
+            assert!((a - b * gain).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::settings::DitherMode;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Quantizes float samples to integers with optional dither.
+/// `max` is the largest positive integer value of the target format.
+pub struct Quantizer {
+    mode: DitherMode,
+    channels: usize,
+    max: f64,
+    rng: StdRng,
+    // The last two quantization errors of every channel, for noise shaping
+    errors: Vec<[f64; 2]>,
+    index: usize,
+}
+
+impl Quantizer {
+    pub fn new(mode: DitherMode, channels: u16, max: i32) -> Self {
+        Self {
+            mode,
+            channels: channels as usize,
+            max: max as f64,
+            rng: StdRng::from_entropy(),
+            errors: vec![[0.0; 2]; channels as usize],
+            index: 0,
+        }
+    }
+
+    fn tpdf(&mut self) -> f64 {
+        self.rng.gen::<f64>() - self.rng.gen::<f64>()
+    }
+
+    pub fn quantize(&mut self, sample: f32) -> i32 {
+        let channel = self.index;
+        self.index = (self.index + 1) % self.channels;
+
+        let scaled = sample as f64 * self.max;
+        let value = match self.mode {
+            DitherMode::None => scaled.round(),
+            DitherMode::Tpdf => (scaled + self.tpdf()).round(),
+            DitherMode::NoiseShaped => {
+                // Second order error feedback, which moves the dither noise
+                // to the high frequencies where it is less audible
+                let [e1, e2] = self.errors[channel];
+                let shaped = scaled - (2.0 * e1 - e2);
+                let value = (shaped + self.tpdf()).round();
+                let error = (value - shaped).clamp(-2.0, 2.0);
+                self.errors[channel] = [error, e1];
+                value
+            }
+        };
+
+        value.clamp(-self.max - 1.0, self.max) as i32
+    }
+}
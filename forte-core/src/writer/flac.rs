@@ -0,0 +1,79 @@
+use crate::errors::error_types::MIDIRendererError;
+use crate::settings::DitherMode;
+use crate::writer::dither::Quantizer;
+use crate::writer::AudioWriter;
+use flac_bound::FlacEncoder;
+use std::path::PathBuf;
+use tracing::{error, info};
+
+// FLAC has no float samples, 24 bits keeps quiet renders from losing detail
+const BITS_PER_SAMPLE: u32 = 24;
+const SAMPLE_MAX: i32 = 8_388_607;
+
+pub struct FlacFileWriter {
+    channels: u16,
+    encoder: FlacEncoder<'static>,
+    quantizer: Quantizer,
+    buffer: Vec<i32>,
+}
+
+impl FlacFileWriter {
+    pub fn new(
+        channels: u16,
+        sample_rate: u32,
+        compression_level: u8,
+        dither: DitherMode,
+        filepath: PathBuf,
+    ) -> Result<Self, MIDIRendererError> {
+        let encoder = match FlacEncoder::new() {
+            Some(e) => e,
+            None => {
+                error!("Unable to create FLAC encoder");
+                return Err(MIDIRendererError::Writer(
+                    "Unable to create FLAC encoder".to_owned(),
+                ));
+            }
+        };
+        info!("Creating new FLAC encoder");
+
+        let encoder = encoder
+            .channels(channels as u32)
+            .bits_per_sample(BITS_PER_SAMPLE)
+            .sample_rate(sample_rate)
+            .compression_level(compression_level.min(8) as u32)
+            .init_file(&filepath)
+            .map_err(|err| {
+                error!("Unable to create FLAC encoder: {:?}", err);
+                MIDIRendererError::Writer(format!("{err:?}"))
+            })?;
+
+        Ok(Self {
+            channels,
+            encoder,
+            quantizer: Quantizer::new(dither, channels, SAMPLE_MAX),
+            buffer: Vec::new(),
+        })
+    }
+}
+
+impl AudioWriter for FlacFileWriter {
+    fn write_samples(&mut self, samples: Vec<f32>) -> Result<(), MIDIRendererError> {
+        self.buffer.clear();
+        let quantizer = &mut self.quantizer;
+        self.buffer
+            .extend(samples.iter().map(|s| quantizer.quantize(*s)));
+
+        let frames = self.buffer.len() / self.channels as usize;
+        self.encoder
+            .process_interleaved(&self.buffer, frames as u32)
+            .map_err(|state| MIDIRendererError::Writer(format!("{state:?}")))
+    }
+
+    fn finalize(self: Box<Self>) -> Result<(), MIDIRendererError> {
+        info!("Finalizing FLAC audio file");
+        self.encoder
+            .finish()
+            .map_err(|encoder| MIDIRendererError::Writer(format!("{:?}", encoder.state())))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use crate::errors::error_types::MIDIRendererError;
+use crate::writer::AudioWriter;
+use audiopus::coder::Encoder;
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use rubato::{FftFixedIn, Resampler};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use tracing::{error, info};
+
+// Ogg Opus granule positions are always counted at 48 kHz
+const GRANULE_RATE: u32 = 48_000;
+const SUPPORTED_SAMPLE_RATES: [u32; 5] = [8_000, 12_000, 16_000, 24_000, 48_000];
+const RESAMPLER_CHUNK_SIZE: usize = 1024;
+// The largest packet libopus can produce for one frame
+const MAX_PACKET_SIZE: usize = 4000;
+
+pub struct OpusFileWriter {
+    channels: u16,
+    encoder: Encoder,
+    writer: PacketWriter<'static, BufWriter<File>>,
+    serial: u32,
+    resampler: Option<FftFixedIn<f32>>,
+    resampler_input: Vec<Vec<f32>>,
+
+    frame_size: usize,
+    frame_buffer: Vec<f32>,
+    packet_buffer: Vec<u8>,
+    pending_packet: Option<Vec<u8>>,
+
+    // In samples at 48 kHz, like the granule positions
+    pre_skip: u64,
+    granule_pos: u64,
+    // Samples per channel received from the renderer, at the original sample rate
+    input_len: u64,
+    input_rate: u32,
+}
+
+impl OpusFileWriter {
+    pub fn new(
+        channels: u16,
+        sample_rate: u32,
+        bitrate: u32,
+        complexity: u8,
+        filepath: PathBuf,
+    ) -> Result<Self, MIDIRendererError> {
+        let err_map = |err: audiopus::Error| {
+            error!("Unable to create Opus encoder: {}", err.to_string());
+            MIDIRendererError::Writer(err.to_string())
+        };
+
+        let (encoder_rate, resampler) = if SUPPORTED_SAMPLE_RATES.contains(&sample_rate) {
+            (sample_rate, None)
+        } else {
+            info!("Resampling from {sample_rate}Hz to 48000Hz for Opus");
+            let resampler = FftFixedIn::new(
+                sample_rate as usize,
+                GRANULE_RATE as usize,
+                RESAMPLER_CHUNK_SIZE,
+                2,
+                channels as usize,
+            )
+            .map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+            (GRANULE_RATE, Some(resampler))
+        };
+
+        info!("Creating new Opus encoder");
+        let opus_rate = match encoder_rate {
+            8_000 => SampleRate::Hz8000,
+            12_000 => SampleRate::Hz12000,
+            16_000 => SampleRate::Hz16000,
+            24_000 => SampleRate::Hz24000,
+            _ => SampleRate::Hz48000,
+        };
+        let opus_channels = if channels == 1 {
+            Channels::Mono
+        } else {
+            Channels::Stereo
+        };
+
+        let mut encoder =
+            Encoder::new(opus_rate, opus_channels, Application::Audio).map_err(err_map)?;
+        encoder
+            .set_bitrate(Bitrate::BitsPerSecond(bitrate as i32))
+            .map_err(err_map)?;
+        encoder
+            .set_complexity(complexity.min(10))
+            .map_err(err_map)?;
+
+        // Both the encoder and the resampler delay the signal, the player skips
+        // that many samples at the start
+        let ratio = (GRANULE_RATE / encoder_rate) as u64;
+        let mut pre_skip = encoder.lookahead().map_err(err_map)? as u64 * ratio;
+        if let Some(resampler) = &resampler {
+            pre_skip += resampler.output_delay() as u64;
+        }
+
+        let file =
+            File::create(filepath).map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+        let mut writer = PacketWriter::new(BufWriter::new(file));
+        let serial = rand::random();
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1);
+        head.push(channels as u8);
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+
+        let vendor = concat!("Forte ", env!("CARGO_PKG_VERSION"));
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes());
+
+        for header in [head, tags] {
+            writer
+                .write_packet(header, serial, PacketWriteEndInfo::EndPage, 0)
+                .map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+        }
+
+        Ok(Self {
+            channels,
+            encoder,
+            writer,
+            serial,
+            resampler,
+            resampler_input: vec![Vec::new(); channels as usize],
+
+            // 20ms frames
+            frame_size: encoder_rate as usize / 50,
+            frame_buffer: Vec::new(),
+            packet_buffer: vec![0; MAX_PACKET_SIZE],
+            pending_packet: None,
+
+            pre_skip,
+            // The pre-skip samples are decoded too, so they are counted by the packets
+            granule_pos: 0,
+            input_len: 0,
+            input_rate: sample_rate,
+        })
+    }
+
+    fn resample(&mut self, samples: &[f32], flush: bool) -> Result<Vec<f32>, MIDIRendererError> {
+        let resampler = match self.resampler.as_mut() {
+            Some(resampler) => resampler,
+            None => return Ok(samples.to_vec()),
+        };
+
+        let channels = self.channels as usize;
+        for (i, input) in self.resampler_input.iter_mut().enumerate() {
+            input.extend(samples.iter().skip(i).step_by(channels));
+        }
+
+        let mut output: Vec<Vec<f32>> = vec![Vec::new(); channels];
+        let map_err = |err: rubato::ResampleError| MIDIRendererError::Writer(err.to_string());
+
+        while self.resampler_input[0].len() >= resampler.input_frames_next() {
+            let len = resampler.input_frames_next();
+            let chunk: Vec<Vec<f32>> = self
+                .resampler_input
+                .iter_mut()
+                .map(|input| input.drain(..len).collect())
+                .collect();
+            let resampled = resampler.process(&chunk, None).map_err(map_err)?;
+            for (out, resampled) in output.iter_mut().zip(resampled) {
+                out.extend(resampled);
+            }
+        }
+
+        if flush {
+            let rest: Vec<Vec<f32>> = self
+                .resampler_input
+                .iter_mut()
+                .map(std::mem::take)
+                .collect();
+            let mut chunks = vec![resampler.process_partial(Some(rest.as_slice()), None)];
+            // Pushes the samples still held back by the resampler delay
+            chunks.push(resampler.process_partial::<Vec<f32>>(None, None));
+            for resampled in chunks {
+                for (out, resampled) in output.iter_mut().zip(resampled.map_err(map_err)?) {
+                    out.extend(resampled);
+                }
+            }
+        }
+
+        let mut interleaved = Vec::with_capacity(output[0].len() * channels);
+        for i in 0..output[0].len() {
+            for out in &output {
+                interleaved.push(out[i]);
+            }
+        }
+        Ok(interleaved)
+    }
+
+    fn encode_frames(&mut self) -> Result<(), MIDIRendererError> {
+        let frame_len = self.frame_size * self.channels as usize;
+        let ratio = (GRANULE_RATE as usize / (self.frame_size * 50)) as u64;
+
+        while self.frame_buffer.len() >= frame_len {
+            let frame: Vec<f32> = self.frame_buffer.drain(..frame_len).collect();
+            let size = self
+                .encoder
+                .encode_float(&frame, &mut self.packet_buffer)
+                .map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+
+            // The last packet has to be marked as the end of the stream, so every
+            // packet is held back until the next one is encoded
+            if let Some(packet) = self.pending_packet.take() {
+                self.writer
+                    .write_packet(
+                        packet,
+                        self.serial,
+                        PacketWriteEndInfo::NormalPacket,
+                        self.granule_pos,
+                    )
+                    .map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+            }
+            self.pending_packet = Some(self.packet_buffer[..size].to_vec());
+            self.granule_pos += self.frame_size as u64 * ratio;
+        }
+
+        Ok(())
+    }
+}
+
+impl AudioWriter for OpusFileWriter {
+    fn write_samples(&mut self, samples: Vec<f32>) -> Result<(), MIDIRendererError> {
+        self.input_len += (samples.len() / self.channels as usize) as u64;
+        let samples = self.resample(&samples, false)?;
+        self.frame_buffer.extend(samples);
+        self.encode_frames()
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<(), MIDIRendererError> {
+        info!("Finalizing Opus audio file");
+        let samples = self.resample(&[], true)?;
+        self.frame_buffer.extend(samples);
+
+        // The encoder holds back the last pre-skip samples, silence pushes them out
+        let ratio = (GRANULE_RATE as usize / (self.frame_size * 50)) as u64;
+        let flush = self.pre_skip.div_ceil(ratio) as usize * self.channels as usize;
+        self.frame_buffer.extend(std::iter::repeat(0.0).take(flush));
+
+        let frame_len = self.frame_size * self.channels as usize;
+        let padding = (frame_len - self.frame_buffer.len() % frame_len) % frame_len;
+        self.frame_buffer
+            .extend(std::iter::repeat(0.0).take(padding));
+        self.encode_frames()?;
+
+        // The end granule position trims the silence and the padding of the last frame
+        let end_granule =
+            self.pre_skip + self.input_len * GRANULE_RATE as u64 / self.input_rate as u64;
+        let packet = self.pending_packet.take().unwrap_or_default();
+        self.writer
+            .write_packet(
+                packet,
+                self.serial,
+                PacketWriteEndInfo::EndStream,
+                end_granule.min(self.granule_pos),
+            )
+            .map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+
+        Ok(())
+    }
+}
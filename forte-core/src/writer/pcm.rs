@@ -0,0 +1,431 @@
+use crate::errors::error_types::MIDIRendererError;
+use crate::settings::{DitherMode, PCMSampleFormat, WavContainer};
+use crate::writer::dither::Quantizer;
+use crate::writer::AudioWriter;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use tracing::{error, info};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// The sub format GUIDs of extensible files are the format tag followed by these bytes
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+// Largest size a plain RIFF file can describe
+const RIFF_LIMIT: u64 = u32::MAX as u64;
+
+// Sony Wave64 chunk GUIDs, in the byte order they are stored in the file
+const W64_RIFF: [u8; 16] = [
+    0x72, 0x69, 0x66, 0x66, 0x2E, 0x91, 0xCF, 0x11, 0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00,
+];
+const W64_WAVE: [u8; 16] = [
+    0x77, 0x61, 0x76, 0x65, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A,
+];
+const W64_FMT: [u8; 16] = [
+    0x66, 0x6D, 0x74, 0x20, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A,
+];
+const W64_DATA: [u8; 16] = [
+    0x64, 0x61, 0x74, 0x61, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A,
+];
+
+fn align_w64(len: u64) -> u64 {
+    (len + 7) / 8 * 8
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Container {
+    Riff,
+    Rf64,
+    Wave64,
+}
+
+pub struct PCMFileWriter {
+    writer: BufWriter<File>,
+    format: PCMSampleFormat,
+    container: Container,
+    quantizer: Option<Quantizer>,
+    channels: u16,
+    sample_rate: u32,
+    data_len: u64,
+    buffer: Vec<u8>,
+}
+
+impl PCMFileWriter {
+    pub fn new(
+        channels: u16,
+        sample_rate: u32,
+        format: PCMSampleFormat,
+        dither: DitherMode,
+        container: WavContainer,
+        estimated_frames: Option<u64>,
+        filepath: PathBuf,
+    ) -> Result<Self, MIDIRendererError> {
+        info!("Creating new PCM writer");
+        let quantizer = match format {
+            PCMSampleFormat::Int16 => Some(Quantizer::new(dither, channels, i16::MAX as i32)),
+            PCMSampleFormat::Int24 => Some(Quantizer::new(dither, channels, 8_388_607)),
+            PCMSampleFormat::Float32 | PCMSampleFormat::Float64 => None,
+        };
+
+        let file = File::create(filepath).map_err(|err| {
+            error!("Unable to create PCM writer: {}", &err.to_string());
+            MIDIRendererError::Writer(err.to_string())
+        })?;
+
+        let mut writer = Self {
+            writer: BufWriter::new(file),
+            format,
+            container: Container::Riff,
+            quantizer,
+            channels,
+            sample_rate,
+            data_len: 0,
+            buffer: Vec::new(),
+        };
+
+        writer.container = match container {
+            WavContainer::Wave64 => Container::Wave64,
+            WavContainer::Auto => {
+                let estimated_len = estimated_frames.unwrap_or(0) * writer.block_align() as u64;
+                if estimated_len > RIFF_LIMIT {
+                    info!("The estimated size exceeds the RIFF limit, writing RF64");
+                    Container::Rf64
+                } else {
+                    Container::Riff
+                }
+            }
+        };
+
+        // Written again with the real sizes when the file is finalized
+        writer.write_header()?;
+
+        Ok(writer)
+    }
+
+    fn bytes_per_sample(&self) -> u16 {
+        match self.format {
+            PCMSampleFormat::Int16 => 2,
+            PCMSampleFormat::Int24 => 3,
+            PCMSampleFormat::Float32 => 4,
+            PCMSampleFormat::Float64 => 8,
+        }
+    }
+
+    fn block_align(&self) -> u16 {
+        self.channels * self.bytes_per_sample()
+    }
+
+    fn is_float(&self) -> bool {
+        !self.format.is_integer()
+    }
+
+    // Samples over 16 bits and more than 2 channels need WAVE_FORMAT_EXTENSIBLE,
+    // as players can't tell the bit depth or the speaker layout otherwise
+    fn is_extensible(&self) -> bool {
+        self.bytes_per_sample() > 2 || self.channels > 2
+    }
+
+    // The speakers of the usual layouts, from mono to 7.1
+    fn channel_mask(&self) -> u32 {
+        match self.channels {
+            1 => 0x4,
+            2 => 0x3,
+            3 => 0x7,
+            4 => 0x33,
+            5 => 0x37,
+            6 => 0x3F,
+            7 => 0x70F,
+            8 => 0x63F,
+            _ => 0,
+        }
+    }
+
+    fn fmt_body(&self) -> Vec<u8> {
+        let block_align = self.block_align();
+        let bits = self.bytes_per_sample() * 8;
+        let format = if self.is_float() {
+            WAVE_FORMAT_IEEE_FLOAT
+        } else {
+            WAVE_FORMAT_PCM
+        };
+        let tag = if self.is_extensible() {
+            WAVE_FORMAT_EXTENSIBLE
+        } else {
+            format
+        };
+
+        let mut fmt = Vec::with_capacity(40);
+        fmt.extend_from_slice(&tag.to_le_bytes());
+        fmt.extend_from_slice(&self.channels.to_le_bytes());
+        fmt.extend_from_slice(&self.sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+
+        if self.is_extensible() {
+            fmt.extend_from_slice(&22u16.to_le_bytes());
+            fmt.extend_from_slice(&bits.to_le_bytes());
+            fmt.extend_from_slice(&self.channel_mask().to_le_bytes());
+            fmt.extend_from_slice(&format.to_le_bytes());
+            fmt.extend_from_slice(&SUBFORMAT_GUID_TAIL);
+        } else if self.is_float() {
+            // Float files carry the extension size field
+            fmt.extend_from_slice(&0u16.to_le_bytes());
+        }
+        fmt
+    }
+
+    // RIFF and RF64 headers have the same length, so a file can still be turned
+    // into RF64 when it is finalized, if the estimate was too low
+    fn riff_header(&self) -> Vec<u8> {
+        let rf64 = self.container == Container::Rf64;
+        let frames = self.data_len / self.block_align() as u64;
+        let fmt = self.fmt_body();
+        let fact_len = if self.is_float() { 12 } else { 0 };
+        let riff_len =
+            4 + 36 + 8 + fmt.len() as u64 + fact_len + 8 + self.data_len + self.data_len % 2;
+
+        let mut header = Vec::with_capacity(80);
+        if rf64 {
+            header.extend_from_slice(b"RF64");
+            header.extend_from_slice(&u32::MAX.to_le_bytes());
+        } else {
+            header.extend_from_slice(b"RIFF");
+            header.extend_from_slice(&(riff_len as u32).to_le_bytes());
+        }
+        header.extend_from_slice(b"WAVE");
+
+        if rf64 {
+            header.extend_from_slice(b"ds64");
+            header.extend_from_slice(&28u32.to_le_bytes());
+            header.extend_from_slice(&riff_len.to_le_bytes());
+            header.extend_from_slice(&self.data_len.to_le_bytes());
+            header.extend_from_slice(&frames.to_le_bytes());
+            header.extend_from_slice(&0u32.to_le_bytes());
+        } else {
+            header.extend_from_slice(b"JUNK");
+            header.extend_from_slice(&28u32.to_le_bytes());
+            header.extend_from_slice(&[0; 28]);
+        }
+
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        header.extend_from_slice(&fmt);
+
+        if self.is_float() {
+            header.extend_from_slice(b"fact");
+            header.extend_from_slice(&4u32.to_le_bytes());
+            header.extend_from_slice(&(frames.min(RIFF_LIMIT) as u32).to_le_bytes());
+        }
+
+        header.extend_from_slice(b"data");
+        if rf64 {
+            header.extend_from_slice(&u32::MAX.to_le_bytes());
+        } else {
+            header.extend_from_slice(&(self.data_len as u32).to_le_bytes());
+        }
+        header
+    }
+
+    fn wave64_header(&self) -> Vec<u8> {
+        let mut fmt = self.fmt_body();
+        // Wave64 chunks are aligned to 8 bytes
+        let fmt_len = 24 + fmt.len() as u64;
+        fmt.resize(align_w64(fmt.len() as u64) as usize, 0);
+
+        let header_len = 40 + 24 + fmt.len() as u64 + 24;
+        let file_len = header_len + align_w64(self.data_len);
+
+        let mut header = Vec::with_capacity(header_len as usize);
+        header.extend_from_slice(&W64_RIFF);
+        header.extend_from_slice(&file_len.to_le_bytes());
+        header.extend_from_slice(&W64_WAVE);
+
+        header.extend_from_slice(&W64_FMT);
+        header.extend_from_slice(&fmt_len.to_le_bytes());
+        header.extend_from_slice(&fmt);
+
+        header.extend_from_slice(&W64_DATA);
+        header.extend_from_slice(&(24 + self.data_len).to_le_bytes());
+        header
+    }
+
+    fn write_header(&mut self) -> Result<(), MIDIRendererError> {
+        let header = match self.container {
+            Container::Riff | Container::Rf64 => self.riff_header(),
+            Container::Wave64 => self.wave64_header(),
+        };
+
+        self.writer
+            .write_all(&header)
+            .map_err(|err| MIDIRendererError::Writer(err.to_string()))
+    }
+}
+
+impl AudioWriter for PCMFileWriter {
+    fn write_samples(&mut self, samples: Vec<f32>) -> Result<(), MIDIRendererError> {
+        self.buffer.clear();
+        match (self.format, self.quantizer.as_mut()) {
+            (PCMSampleFormat::Int16, Some(quantizer)) => {
+                for sample in samples {
+                    let sample = quantizer.quantize(sample) as i16;
+                    self.buffer.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+            (PCMSampleFormat::Int24, Some(quantizer)) => {
+                for sample in samples {
+                    let sample = quantizer.quantize(sample);
+                    self.buffer.extend_from_slice(&sample.to_le_bytes()[..3]);
+                }
+            }
+            (PCMSampleFormat::Float64, _) => {
+                for sample in samples {
+                    self.buffer
+                        .extend_from_slice(&(sample as f64).to_le_bytes());
+                }
+            }
+            _ => {
+                for sample in samples {
+                    self.buffer.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+        }
+
+        self.data_len += self.buffer.len() as u64;
+        self.writer
+            .write_all(&self.buffer)
+            .map_err(|err| MIDIRendererError::Writer(err.to_string()))
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<(), MIDIRendererError> {
+        info!("Finalizing PCM audio file");
+        // Chunks have to be word aligned in RIFF files and 8 byte aligned in Wave64
+        let padding = match self.container {
+            Container::Riff | Container::Rf64 => self.data_len % 2,
+            Container::Wave64 => align_w64(self.data_len) - self.data_len,
+        };
+        self.writer
+            .write_all(&vec![0; padding as usize])
+            .map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+
+        if self.container == Container::Riff
+            && self.riff_header().len() as u64 + self.data_len > RIFF_LIMIT
+        {
+            info!("The audio data exceeds the RIFF limit, switching to RF64");
+            self.container = Container::Rf64;
+        }
+
+        self.writer
+            .seek(SeekFrom::Start(0))
+            .map_err(|err| MIDIRendererError::Writer(err.to_string()))?;
+        self.write_header()?;
+        self.writer
+            .flush()
+            .map_err(|err| MIDIRendererError::Writer(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+    }
+
+    // Writes a short file and returns it
+    fn write(format: PCMSampleFormat, channels: u16, container: WavContainer) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "forte-pcm-{}-{format:?}-{channels}-{container:?}.wav",
+            std::process::id()
+        ));
+        let mut writer = Box::new(
+            PCMFileWriter::new(
+                channels,
+                48000,
+                format,
+                DitherMode::None,
+                container,
+                None,
+                path.clone(),
+            )
+            .unwrap(),
+        );
+        writer.write_samples(vec![0.5; 30]).unwrap();
+        writer.finalize().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    // Returns the body of the `fmt ` chunk of a RIFF file
+    fn fmt(file: &[u8]) -> &[u8] {
+        let pos = file.windows(4).position(|w| w == b"fmt ").unwrap();
+        let len = u32_at(file, pos + 4) as usize;
+        &file[pos + 8..pos + 8 + len]
+    }
+
+    #[test]
+    fn plain_format_for_16_bit_stereo() {
+        let file = write(PCMSampleFormat::Int16, 2, WavContainer::Auto);
+        let fmt = fmt(&file);
+        assert_eq!(fmt.len(), 16);
+        assert_eq!(u16_at(fmt, 0), WAVE_FORMAT_PCM);
+        assert_eq!(u32_at(&file, 4) as usize, file.len() - 8);
+    }
+
+    #[test]
+    fn extensible_format_for_24_bit() {
+        let file = write(PCMSampleFormat::Int24, 2, WavContainer::Auto);
+        let fmt = fmt(&file);
+        assert_eq!(fmt.len(), 40);
+        assert_eq!(u16_at(fmt, 0), WAVE_FORMAT_EXTENSIBLE);
+        assert_eq!(u16_at(fmt, 12), 6);
+        assert_eq!(u16_at(fmt, 14), 24);
+        assert_eq!(u16_at(fmt, 16), 22);
+        assert_eq!(u16_at(fmt, 18), 24);
+        assert_eq!(u32_at(fmt, 20), 0x3);
+        assert_eq!(u16_at(fmt, 24), WAVE_FORMAT_PCM);
+        assert_eq!(&fmt[26..], &SUBFORMAT_GUID_TAIL);
+
+        // 30 samples of 3 bytes
+        let data = file.windows(4).position(|w| w == b"data").unwrap();
+        assert_eq!(u32_at(&file, data + 4), 90);
+        assert_eq!(file.len(), data + 8 + 90);
+        assert_eq!(u32_at(&file, 4) as usize, file.len() - 8);
+    }
+
+    #[test]
+    fn extensible_float_keeps_the_fact_chunk() {
+        let file = write(PCMSampleFormat::Float32, 1, WavContainer::Auto);
+        let fmt = fmt(&file);
+        assert_eq!(u16_at(fmt, 0), WAVE_FORMAT_EXTENSIBLE);
+        assert_eq!(u32_at(fmt, 20), 0x4);
+        assert_eq!(u16_at(fmt, 24), WAVE_FORMAT_IEEE_FLOAT);
+
+        let fact = file.windows(4).position(|w| w == b"fact").unwrap();
+        assert_eq!(u32_at(&file, fact + 8), 30);
+        assert_eq!(u32_at(&file, 4) as usize, file.len() - 8);
+    }
+
+    #[test]
+    fn extensible_wave64() {
+        let file = write(PCMSampleFormat::Int24, 2, WavContainer::Wave64);
+        let fmt_len = u64::from_le_bytes(file[56..64].try_into().unwrap());
+        assert_eq!(fmt_len, 24 + 40);
+        assert_eq!(u16_at(&file, 64), WAVE_FORMAT_EXTENSIBLE);
+
+        let file_len = u64::from_le_bytes(file[16..24].try_into().unwrap());
+        assert_eq!(file_len as usize, file.len());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::errors::error_types::MIDIRendererError;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use tracing::info;
+
+/// Temporary file that holds the processed samples of a two pass render until
+/// the whole output was analyzed. The file is deleted when the spool is dropped.
+pub struct SampleSpool {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl SampleSpool {
+    pub fn new(path: PathBuf) -> Result<Self, MIDIRendererError> {
+        info!("Spooling samples to {:?}", path);
+        let file = File::create(&path).map_err(|e| MIDIRendererError::Writer(e.to_string()))?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), MIDIRendererError> {
+        for sample in samples {
+            self.writer
+                .write_all(&sample.to_le_bytes())
+                .map_err(|e| MIDIRendererError::Writer(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Reads every sample back in chunks of `chunk_len` samples
+    pub fn replay(
+        mut self,
+        chunk_len: usize,
+        mut f: impl FnMut(Vec<f32>) -> Result<(), MIDIRendererError>,
+    ) -> Result<(), MIDIRendererError> {
+        self.writer
+            .flush()
+            .map_err(|e| MIDIRendererError::Writer(e.to_string()))?;
+
+        let file = File::open(&self.path).map_err(|e| MIDIRendererError::Writer(e.to_string()))?;
+        let mut reader = BufReader::new(file);
+        let mut bytes = vec![0u8; chunk_len * 4];
+
+        loop {
+            let mut len = 0;
+            while len < bytes.len() {
+                let read = reader
+                    .read(&mut bytes[len..])
+                    .map_err(|e| MIDIRendererError::Writer(e.to_string()))?;
+                if read == 0 {
+                    break;
+                }
+                len += read;
+            }
+            if len == 0 {
+                break;
+            }
+
+            let samples = bytes[..len]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            f(samples)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SampleSpool {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).unwrap_or_default();
+    }
+}
@@ -0,0 +1,12 @@
+pub mod renderers;
+
+mod render_manager;
+pub use render_manager::*;
+mod midi_pool;
+pub use midi_pool::{MIDIInput, MIDIRendererStatus};
+mod drums;
+mod note_dropper;
+mod note_filter;
+mod rpn;
+mod soundfont_pool;
+mod sysex;
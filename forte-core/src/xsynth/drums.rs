@@ -0,0 +1,178 @@
+use super::sysex::default_drum_channels;
+use crate::settings::CHANNELS_PER_PORT;
+use xsynth_core::channel::ControlEvent;
+
+// xsynth plays the drum kits from this bank
+const DRUM_BANK: u8 = 128;
+
+/// Follows which channels play drums while the MIDI plays. GS SysEx can turn any
+/// channel of a port into a drum channel or back, but xsynth only knows drum
+/// channels from their init options. The other channels select the drum bank
+/// instead, right before their next note so unused channels are not created.
+pub struct DrumChannels {
+    // Channels set to drums in the settings, which xsynth creates as drum channels
+    fixed: Vec<bool>,
+    // Whether each channel of each port plays drums
+    drums: Vec<bool>,
+
+    // The state of every synth channel, for all instances. The bank is the last
+    // one selected by the MIDI, which is restored when the drums are turned off.
+    bank: Vec<u8>,
+    synth_drums: Vec<bool>,
+}
+
+impl DrumChannels {
+    /// `fixed` has the drum setting of every channel of every port
+    pub fn new(fixed: Vec<bool>, instances: usize) -> Self {
+        let synth_channels = fixed.len() * instances;
+        let mut drums = Self {
+            drums: fixed.clone(),
+            fixed,
+            bank: vec![0; synth_channels],
+            synth_drums: vec![false; synth_channels],
+        };
+        drums.reset();
+        drums
+    }
+
+    /// Goes back to the default drum channels after a GM, GS or XG reset, which
+    /// also resets the banks of the synth channels
+    pub fn reset(&mut self) {
+        let defaults = default_drum_channels();
+        for (i, drums) in self.drums.iter_mut().enumerate() {
+            *drums = self.fixed[i] || defaults[i % CHANNELS_PER_PORT];
+        }
+        self.bank.fill(0);
+        self.synth_drums.fill(false);
+    }
+
+    /// Resets one synth channel after "reset all controllers", which sets its bank to 0
+    pub fn reset_channel(&mut self, index: usize) {
+        self.bank[index] = 0;
+        self.synth_drums[index] = false;
+    }
+
+    /// Whether the channel, counted over all ports, plays drums
+    pub fn is_drums(&self, channel: usize) -> bool {
+        self.drums[channel]
+    }
+
+    /// Turns a channel of a port into a drum channel or back, for all instances
+    pub fn set(&mut self, channel: usize, drums: bool) {
+        if !self.fixed[channel] {
+            self.drums[channel] = drums;
+        }
+    }
+
+    /// Returns the event for a bank select on the synth channel `index`, or `None`
+    /// if the channel plays drums and keeps the drum bank
+    pub fn bank_select(&mut self, channel: usize, index: usize, bank: u8) -> Option<ControlEvent> {
+        self.bank[index] = bank;
+        if self.drums[channel] {
+            None
+        } else {
+            self.synth_drums[index] = false;
+            Some(ControlEvent::Raw(0x00, bank))
+        }
+    }
+
+    /// Returns the bank select to send before a note on the synth channel `index`,
+    /// if the channel was turned into a drum channel or back since its last note
+    pub fn sync(&mut self, channel: usize, index: usize) -> Option<ControlEvent> {
+        let drums = self.drums[channel];
+        if self.fixed[channel] || self.synth_drums[index] == drums {
+            return None;
+        }
+
+        self.synth_drums[index] = drums;
+        let bank = if drums { DRUM_BANK } else { self.bank[index] };
+        Some(ControlEvent::Raw(0x00, bank))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank(event: Option<ControlEvent>) -> u8 {
+        match event {
+            Some(ControlEvent::Raw(0x00, bank)) => bank,
+            other => panic!("Expected a bank select, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn channel_10_plays_drums_on_every_port() {
+        let mut drums = DrumChannels::new(vec![false; 32], 1);
+        assert!(drums.is_drums(9));
+        assert!(drums.is_drums(25));
+        assert!(!drums.is_drums(0));
+        assert!(!drums.is_drums(16));
+
+        assert_eq!(bank(drums.sync(9, 9)), DRUM_BANK);
+        assert!(drums.sync(9, 9).is_none());
+        assert!(drums.sync(0, 0).is_none());
+    }
+
+    #[test]
+    fn rhythm_part_only_changes_its_port() {
+        let mut drums = DrumChannels::new(vec![false; 32], 1);
+        drums.set(16 + 3, true);
+        assert!(drums.is_drums(19));
+        assert!(!drums.is_drums(3));
+
+        drums.set(9, false);
+        assert!(!drums.is_drums(9));
+        assert!(drums.is_drums(25));
+    }
+
+    #[test]
+    fn drums_apply_to_every_instance() {
+        let mut drums = DrumChannels::new(vec![false; 16], 2);
+        drums.set(3, true);
+        assert_eq!(bank(drums.sync(3, 3)), DRUM_BANK);
+        assert_eq!(bank(drums.sync(3, 16 + 3)), DRUM_BANK);
+    }
+
+    #[test]
+    fn melodic_bank_comes_back_after_the_drums() {
+        let mut drums = DrumChannels::new(vec![false; 16], 1);
+        assert_eq!(bank(drums.bank_select(0, 0, 5)), 5);
+        assert!(drums.sync(0, 0).is_none());
+
+        drums.set(0, true);
+        assert_eq!(bank(drums.sync(0, 0)), DRUM_BANK);
+        // Drum channels keep the drum bank
+        assert!(drums.bank_select(0, 0, 8).is_none());
+
+        drums.set(0, false);
+        assert_eq!(bank(drums.sync(0, 0)), 8);
+        assert!(drums.sync(0, 0).is_none());
+    }
+
+    #[test]
+    fn drum_bank_is_selected_again_after_a_reset() {
+        let mut drums = DrumChannels::new(vec![false; 16], 1);
+        assert_eq!(bank(drums.sync(9, 9)), DRUM_BANK);
+        drums.reset_channel(9);
+        assert_eq!(bank(drums.sync(9, 9)), DRUM_BANK);
+
+        drums.set(9, false);
+        drums.set(0, true);
+        drums.reset();
+        assert!(drums.is_drums(9));
+        assert!(!drums.is_drums(0));
+        assert_eq!(bank(drums.sync(9, 9)), DRUM_BANK);
+    }
+
+    #[test]
+    fn fixed_drum_channels_are_left_to_xsynth() {
+        let mut fixed = vec![false; 16];
+        fixed[2] = true;
+        let mut drums = DrumChannels::new(fixed, 1);
+        drums.set(2, false);
+        assert!(drums.is_drums(2));
+        assert!(drums.sync(2, 2).is_none());
+        assert!(drums.bank_select(2, 2, 1).is_none());
+    }
+}
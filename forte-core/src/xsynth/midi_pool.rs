@@ -0,0 +1,1126 @@
+use crate::errors::error_types::MIDIRendererError;
+use crate::midi::{open_midi, MIDISource};
+use crate::settings::{
+    ForteConfig, NoteDropMode, RenderMode, RenderRangeUnit, SingleChannelSettings, SoundfontKey,
+    StemMode, TransposeMode, CHANNELS_PER_PORT,
+};
+use crate::writer::{ForteOutputWriter, RenderedBlock};
+use crate::xsynth::{
+    drums::DrumChannels,
+    note_dropper::NoteDropper,
+    note_filter::filter_notes,
+    renderers::{
+        instance_channel_to_index, ForteBufferedRenderer, ForteStandardRenderer, Renderer,
+        SynthEvent,
+    },
+    rpn::ChannelParameters,
+    sysex::{parse_sysex, SysExMessage},
+    RenderStats,
+};
+use atomic::Atomic;
+use atomic_float::AtomicF64;
+use crossbeam_channel::{Receiver, Sender};
+use midi_toolkit::{
+    events::Event,
+    io::{DiskReader, MIDIFile},
+    pipe,
+    sequence::{
+        event::{
+            cancel_tempo_events, convert_events_into_batches, flatten_track_batches_to_events,
+            scale_event_time, Delta, EventBatch, Track,
+        },
+        unwrap_items, TimeCaster,
+    },
+};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64},
+    Arc, RwLock,
+};
+use std::thread::{self, JoinHandle};
+use tracing::{error, info, warn};
+use xsynth_core::channel::{ChannelAudioEvent, ChannelConfigEvent, ControlEvent};
+use xsynth_core::soundfont::{SampleSoundfont, SoundfontBase};
+use xsynth_core::AudioStreamParams;
+
+// Limits the synth to 256 channels, the most black MIDIs are known to use
+const MAX_PORTS: usize = 16;
+
+// Every track stem renders on its own synth and writes its own file
+const MAX_TRACK_STEMS: usize = 64;
+
+/// A MIDI to render. `length` is its duration in seconds if it is already known,
+/// which is used to estimate the size of the output files. `source` is the MIDI
+/// if it was already opened, so compressed MIDIs are not decompressed again.
+#[derive(Clone, Debug)]
+pub struct MIDIInput {
+    pub path: PathBuf,
+    pub length: Option<f64>,
+    pub source: Option<MIDISource>,
+}
+
+impl From<PathBuf> for MIDIInput {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            length: None,
+            source: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct RenderStatsAtomic {
+    time: Arc<AtomicF64>,
+    voices: Arc<AtomicU64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MIDIRendererStatus {
+    Idle,
+    Rendering,
+    Finished,
+    Error,
+}
+
+struct MIDIRenderer {
+    allow: Arc<AtomicBool>,
+    status: Arc<Atomic<MIDIRendererStatus>>,
+    error: Arc<RwLock<Option<String>>>,
+    soundfonts: Arc<RwLock<HashMap<SoundfontKey, Arc<SampleSoundfont>>>>,
+
+    receiver: Receiver<Delta<f64, EventBatch<Track<Event>>>>,
+    renderer: Box<dyn Renderer>,
+    instances: usize,
+    // Maps every track to the synth instance it is rendered with, if stems are per track
+    track_stems: Option<Vec<usize>>,
+    // The channels that are written to stems, if stems are per channel
+    stem_channels: Option<Vec<usize>>,
+    writer: Option<Sender<RenderedBlock>>,
+    writer_thread: Option<JoinHandle<Result<(), MIDIRendererError>>>,
+
+    audio_params: AudioStreamParams,
+    ignore_range: RangeInclusive<u8>,
+    speed: f64,
+    transpose: i8,
+    transpose_mode: TransposeMode,
+    // The drum channels, which GS SysEx can change while the MIDI plays
+    drums: DrumChannels,
+    // The settings of every channel on every port used by the MIDI
+    channel_settings: Vec<SingleChannelSettings>,
+    // The port each track currently sends to, set by port prefix meta events
+    track_ports: Vec<u8>,
+    // The RPN state of every synth channel, in the same order as the renderer
+    parameters: Vec<ChannelParameters>,
+    // The velocity curve of every channel in `channel_settings`
+    velocity_tables: Vec<[u8; 128]>,
+    note_dropper: Option<NoteDropper>,
+
+    output_vec: Vec<f32>,
+    stems: Vec<Vec<f32>>,
+    missed_samples: f64,
+    time: f64,
+    // The range that is rendered, in seconds
+    start: f64,
+    end: Option<f64>,
+}
+
+// Returns the stem of every track and the names of the stems
+fn track_stems(
+    groups: &[Vec<usize>],
+    track_count: usize,
+) -> Result<(Vec<usize>, Vec<String>), MIDIRendererError> {
+    if groups.is_empty() {
+        if track_count > MAX_TRACK_STEMS {
+            return Err(MIDIRendererError::Renderer(format!(
+                "The MIDI has {track_count} tracks, but at most {MAX_TRACK_STEMS} \
+                track stems are supported. Set track groups to render it."
+            )));
+        }
+        let names = (1..=track_count).map(|i| format!("track{i:02}")).collect();
+        return Ok(((0..track_count).collect(), names));
+    }
+
+    let mut stems = vec![None; track_count];
+    let mut names = Vec::new();
+    for (i, group) in groups.iter().enumerate() {
+        for track in group {
+            match track.checked_sub(1).and_then(|t| stems.get_mut(t)) {
+                Some(stem) => {
+                    stem.get_or_insert(i);
+                }
+                None => warn!("Track {} of group {} is not in the MIDI", track, i + 1),
+            }
+        }
+        names.push(format!("group{:02}", i + 1));
+    }
+
+    let other = names.len();
+    if stems.iter().any(Option::is_none) {
+        names.push("other".to_owned());
+    }
+    if names.len() > MAX_TRACK_STEMS {
+        return Err(MIDIRendererError::Renderer(format!(
+            "The track groups make {} stems, but at most {MAX_TRACK_STEMS} are supported",
+            names.len()
+        )));
+    }
+
+    Ok((
+        stems.into_iter().map(|s| s.unwrap_or(other)).collect(),
+        names,
+    ))
+}
+
+// Returns the transposed key, or `None` if the note is dropped
+fn transpose_key(key: u8, semitones: i8, mode: TransposeMode) -> Option<u8> {
+    let key = key as i16 + semitones as i16;
+    match mode {
+        TransposeMode::Drop => u8::try_from(key).ok().filter(|k| *k < 128),
+        TransposeMode::Clamp => Some(key.clamp(0, 127) as u8),
+    }
+}
+
+// Finds the number of MIDI ports addressed by the port prefix meta events and the
+// channels that play notes on them. Tracks can switch ports anywhere, so the whole
+// MIDI is read.
+fn scan_ports(midi: &MIDIFile<DiskReader>) -> (usize, Vec<bool>) {
+    // A port prefix can address 256 ports, the unsupported ones are folded in below
+    let mut used = vec![false; 256 * CHANNELS_PER_PORT];
+    let mut ports = 1;
+    for track in 0..midi.track_count() {
+        let mut port = 0;
+        for event in midi.iter_track(track as u32) {
+            match event.map(|e| e.event) {
+                Ok(Event::MIDIPort(e)) => {
+                    port = e.channel as usize;
+                    ports = ports.max(port + 1);
+                }
+                Ok(Event::NoteOn(e)) => used[port * CHANNELS_PER_PORT + e.channel as usize] = true,
+                Ok(..) => {}
+                Err(..) => break,
+            }
+        }
+    }
+
+    if ports > MAX_PORTS {
+        warn!("The MIDI uses {ports} ports, only the first {MAX_PORTS} are supported");
+    }
+    let ports = ports.min(MAX_PORTS);
+
+    // The render plays the ports above the supported ones on the port they wrap to
+    let mut channels = vec![false; ports * CHANNELS_PER_PORT];
+    for (i, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+        let port = i / CHANNELS_PER_PORT % ports;
+        channels[port * CHANNELS_PER_PORT + i % CHANNELS_PER_PORT] = true;
+    }
+    (ports, channels)
+}
+
+// Converts a position in ticks to seconds by following the tempo changes of the MIDI
+fn ticks_to_secs<E>(
+    events: impl Iterator<Item = Result<Delta<f64, Track<EventBatch<Event>>>, E>>,
+    ppq: u16,
+    ticks: f64,
+) -> f64 {
+    let secs_per_tick = |tempo: f64| tempo / 1_000_000.0 / ppq as f64;
+
+    let mut tempo = 500_000.0;
+    let mut tick = 0.0;
+    let mut secs = 0.0;
+    for batch in events {
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(..) => break,
+        };
+        if tick + batch.delta >= ticks {
+            break;
+        }
+
+        tick += batch.delta;
+        secs += batch.delta * secs_per_tick(tempo);
+        for event in batch.iter_inner() {
+            if let Event::Tempo(e) = event {
+                tempo = e.tempo as f64;
+            }
+        }
+    }
+
+    secs + (ticks - tick) * secs_per_tick(tempo)
+}
+
+impl MIDIRenderer {
+    pub fn load_new(
+        config: &ForteConfig,
+        midi: MIDIInput,
+        soundfonts: Arc<RwLock<HashMap<SoundfontKey, Arc<SampleSoundfont>>>>,
+        allow: Arc<AtomicBool>,
+        status: Arc<Atomic<MIDIRendererStatus>>,
+        error: Arc<RwLock<Option<String>>>,
+    ) -> Result<Self, MIDIRendererError> {
+        info!("Creating new single MIDI renderer");
+
+        let audio_params = AudioStreamParams::new(
+            config.render_settings.sample_rate,
+            config.render_settings.audio_channels,
+        );
+
+        let midi_path = midi.path;
+        let midi_length = midi.length;
+        let midi = match midi.source {
+            Some(source) => source.open(),
+            None => open_midi(&midi_path),
+        };
+        let midi = midi.map_err(|err| {
+            error!("Error loading MIDI: {:?}", err);
+            MIDIRendererError::Load(err)
+        })?;
+
+        let speed = config.render_settings.speed.clamp(0.5, 4.0);
+        let range = config.render_settings.render_range;
+        let (start, end) = match (range.enabled, range.unit) {
+            (false, _) => (0.0, None),
+            (true, RenderRangeUnit::Seconds) => (range.start, range.end),
+            (true, RenderRangeUnit::Ticks) => {
+                let to_secs = |ticks| {
+                    let events = pipe!(
+                        midi.iter_all_track_events_merged_batches()
+                        |>TimeCaster::<f64>::cast_event_delta()
+                    );
+                    ticks_to_secs(events, midi.ppq(), ticks)
+                };
+                (to_secs(range.start), range.end.map(to_secs))
+            }
+        };
+        // The range is given in MIDI time, so it has to follow the speed
+        let start = start.max(0.0) / speed;
+        let end = end.map(|end| end / speed).filter(|end| {
+            if *end <= start {
+                warn!("The end of the render range is before the start, ignoring it");
+            }
+            *end > start
+        });
+        if range.enabled {
+            info!("Rendering from {:.2}s to {:?}s", start, end);
+        }
+
+        // Only the range is written to the output files
+        let midi_length = midi_length.map(|length| {
+            let length = length / speed;
+            (end.unwrap_or(length).min(length) - start).max(0.0)
+        });
+
+        let (ports, used_channels) = scan_ports(&midi);
+        if ports > 1 {
+            info!("The MIDI uses {ports} ports");
+        }
+
+        let channel_settings = config.synth_settings.unify(ports);
+
+        let (track_stems, stem_channels, stem_names) = match config.render_settings.stem_mode {
+            StemMode::Disabled => (None, None, Vec::new()),
+            StemMode::PerChannel => {
+                // Only the channels that play notes get a file, the others would be silent
+                let channels: Vec<usize> = (0..used_channels.len())
+                    .filter(|&i| used_channels[i])
+                    .collect();
+                info!(
+                    "Rendering {} of {} channels to stems",
+                    channels.len(),
+                    used_channels.len()
+                );
+                let names = channels.iter().map(|i| format!("ch{:02}", i + 1)).collect();
+                (None, Some(channels), names)
+            }
+            StemMode::PerTrack => {
+                let (stems, names) = track_stems(
+                    &config.render_settings.stem_track_groups,
+                    midi.track_count(),
+                )?;
+                info!("Rendering {} tracks to {} stems", stems.len(), names.len());
+                (Some(stems), None, names)
+            }
+        };
+        // The renderer still writes a stem for every channel, even the unused ones
+        let stems = match &stem_channels {
+            Some(..) => vec![Vec::new(); channel_settings.len()],
+            None => vec![Vec::new(); stem_names.len()],
+        };
+
+        // Every track stem needs its own set of channels, as tracks usually share them
+        let instances = if track_stems.is_some() {
+            stem_names.len().max(1)
+        } else {
+            1
+        };
+
+        let (receiver, renderer) = {
+            let ppq = midi.ppq();
+            let key_ranges = channel_settings
+                .iter()
+                .map(|ch| ch.key_range.clone())
+                .collect();
+            // The note filter needs the events one by one and in ticks, they are put
+            // back into batches once the time is converted to seconds
+            let merged = pipe!(
+                midi.iter_all_track_events_merged_batches()
+                |>TimeCaster::<f64>::cast_event_delta()
+                |>flatten_track_batches_to_events()
+                |>filter_notes(config.render_settings.note_filter, key_ranges)
+                |>cancel_tempo_events(250000)
+                |>scale_event_time(1.0 / ppq as f64 / speed)
+                |>convert_events_into_batches()
+                |>unwrap_items()
+            );
+
+            let (midi_snd, midi_rcv) = crossbeam_channel::bounded(100);
+
+            let allow_c1 = allow.clone();
+            thread::spawn(move || {
+                for event in merged {
+                    if !allow_c1.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // The renderer stops reading early if the render range ends before the MIDI
+                    if midi_snd.send(event).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let mut renderer: Box<dyn Renderer> = match config.render_settings.render_mode {
+                RenderMode::RealtimeSimulation => Box::new(ForteBufferedRenderer::new(
+                    config,
+                    &channel_settings,
+                    instances,
+                )),
+                RenderMode::Standard => Box::new(ForteStandardRenderer::new(
+                    config,
+                    &channel_settings,
+                    instances,
+                )),
+            };
+
+            for instance in 0..instances {
+                for (i, ch) in channel_settings.iter().enumerate() {
+                    let layers = if ch.layer_limit_enabled {
+                        Some(ch.layer_limit)
+                    } else {
+                        None
+                    };
+
+                    renderer.send_event(SynthEvent::ChannelConfig(
+                        instance_channel_to_index(instance, i, channel_settings.len()) as u32,
+                        ChannelConfigEvent::SetLayerCount(layers),
+                    ));
+                }
+            }
+
+            (midi_rcv, renderer)
+        };
+
+        let out_filename = if let Some(filename) = midi_path.file_name() {
+            if let Some(filename) = filename.to_str() {
+                filename.to_owned()
+            } else {
+                "out".to_owned()
+            }
+        } else {
+            "out".to_owned()
+        };
+
+        let (writer_snd, writer_rcv) = crossbeam_channel::bounded::<RenderedBlock>(100);
+
+        let allow_c2 = allow.clone();
+        let config_clone = config.clone();
+        let writer_error = Arc::new(AtomicBool::new(false));
+        let writer_errorc = writer_error.clone();
+        let writer_thread = thread::spawn(move || {
+            match ForteOutputWriter::new(&config_clone, out_filename, &stem_names, midi_length) {
+                Ok(mut writer) => {
+                    for block in writer_rcv {
+                        if !allow_c2.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        writer.write_block(block).map_err(|err| {
+                            error!("Error writing audio samples: {}", err);
+                            err
+                        })?;
+                    }
+                    writer.finalize()
+                }
+                Err(err) => {
+                    writer_errorc.store(true, Ordering::Relaxed);
+                    Err(err)
+                }
+            }
+        });
+        thread::sleep(std::time::Duration::from_millis(200));
+        if writer_error.load(Ordering::Relaxed) {
+            return Err(match writer_thread.join() {
+                Ok(Err(err)) => err,
+                _ => MIDIRendererError::Writer("Error creating audio writer".to_owned()),
+            });
+        }
+
+        Ok(Self {
+            allow,
+            status,
+            error,
+            soundfonts,
+
+            receiver,
+            renderer,
+            instances,
+            track_stems,
+            stem_channels,
+            writer: Some(writer_snd),
+            writer_thread: Some(writer_thread),
+
+            audio_params,
+            ignore_range: config.render_settings.vel_ignore_range.clone(),
+            speed,
+            transpose: config.render_settings.transpose,
+            transpose_mode: config.render_settings.transpose_mode,
+            drums: DrumChannels::new(
+                channel_settings
+                    .iter()
+                    .map(|ch| ch.channel_init_options.drums_only)
+                    .collect(),
+                instances,
+            ),
+            parameters: (0..instances)
+                .flat_map(|_| channel_settings.iter())
+                .map(|ch| ChannelParameters::new(ch.pitch_bend_range))
+                .collect(),
+            velocity_tables: channel_settings
+                .iter()
+                .map(|ch| ch.velocity_curve.to_table())
+                .collect(),
+            note_dropper: match config.render_settings.render_mode {
+                RenderMode::RealtimeSimulation
+                    if config.render_settings.note_drop.mode != NoteDropMode::Disabled =>
+                {
+                    Some(NoteDropper::new(
+                        config.render_settings.note_drop,
+                        config.render_settings.realtime_buffer_ms,
+                    ))
+                }
+                _ => None,
+            },
+            channel_settings,
+            track_ports: vec![0; midi.track_count()],
+
+            output_vec: Vec::new(),
+            stems,
+            missed_samples: 0.0,
+            time: 0.0,
+            start,
+            end,
+        })
+    }
+
+    pub fn set_soundfonts(&mut self, config: &ForteConfig) {
+        info!("Applying soundfonts to renderer");
+        let soundfonts = self.soundfonts.read().unwrap();
+
+        let channels = self.channel_settings.len();
+        let ports = channels / CHANNELS_PER_PORT;
+        for (i, ch) in config.synth_settings.unify(ports).into_iter().enumerate() {
+            let mut sfs: Vec<Arc<dyn SoundfontBase>> = vec![];
+            for sf in ch.soundfonts {
+                if let Some(s) = soundfonts.get(&sf.key()) {
+                    sfs.push(s.clone());
+                }
+            }
+            for instance in 0..self.instances {
+                self.renderer.send_event(SynthEvent::ChannelConfig(
+                    instance_channel_to_index(instance, i, channels) as u32,
+                    ChannelConfigEvent::SetSoundfonts(sfs.clone()),
+                ));
+            }
+        }
+    }
+
+    fn read_samples(&mut self, samples: usize) {
+        self.output_vec.resize(samples, 0.0);
+        if self.stems.is_empty() {
+            self.renderer.read_samples(&mut self.output_vec);
+        } else {
+            self.renderer
+                .read_samples_with_stems(&mut self.output_vec, &mut self.stems);
+        }
+    }
+
+    fn send_block(&mut self) {
+        let stems = match &self.stem_channels {
+            Some(channels) => channels
+                .iter()
+                .map(|&i| std::mem::take(&mut self.stems[i]))
+                .collect(),
+            None => self.stems.iter_mut().map(std::mem::take).collect(),
+        };
+        let block = RenderedBlock {
+            mix: self.output_vec.drain(..).collect(),
+            stems,
+        };
+        if let Some(writer) = &self.writer {
+            writer.send(block).unwrap_or_default();
+        }
+    }
+
+    fn render_batch(&mut self, event_time: f64, update_stats: impl FnOnce(f64, u64) + Clone) {
+        let max_batch_time = 0.1;
+        if event_time > max_batch_time {
+            let mut remaining_time = event_time;
+            loop {
+                if remaining_time > max_batch_time {
+                    self.render_batch(max_batch_time, update_stats.clone());
+                    remaining_time -= max_batch_time;
+                } else {
+                    self.render_batch(remaining_time, update_stats);
+                    break;
+                }
+            }
+        } else {
+            let samples = self.audio_params.sample_rate as f64 * event_time + self.missed_samples;
+            self.missed_samples = samples % 1.0;
+            let samples = samples as usize * self.audio_params.channels.count() as usize;
+
+            self.read_samples(samples);
+
+            self.time += event_time;
+            (update_stats)(self.time, self.renderer.voice_count());
+
+            self.send_block();
+        }
+    }
+
+    // Moves the render forward in time. Anything before the start of the render
+    // range is skipped without rendering audio.
+    fn advance(&mut self, delta: f64, update_stats: impl FnOnce(f64, u64) + Clone) {
+        let target = self.time + delta;
+        if self.time < self.start {
+            if target < self.start {
+                self.time = target;
+                (update_stats)(self.time, 0);
+                return;
+            }
+            self.time = self.start;
+        }
+
+        if target > self.time {
+            self.render_batch(target - self.time, update_stats);
+        }
+    }
+
+    fn finalize(&mut self) {
+        info!("Finalizing renderer");
+        if let Some(dropper) = &self.note_dropper {
+            info!(
+                "Skipped {} notes in the realtime simulation",
+                dropper.dropped()
+            );
+        }
+        loop {
+            self.read_samples(self.audio_params.sample_rate as usize);
+            let mut is_empty = true;
+            for s in &self.output_vec {
+                if *s > 0.0001 || *s < -0.0001 {
+                    is_empty = false;
+                    break;
+                }
+            }
+
+            if is_empty {
+                break;
+            }
+
+            self.send_block();
+        }
+
+        // Dropping the sender closes the channel, so the writer thread can finish the file
+        self.writer.take();
+        let result = match self.writer_thread.take() {
+            Some(thread) => thread.join().unwrap_or_else(|_| {
+                Err(MIDIRendererError::Writer(
+                    "The audio writer thread panicked".to_owned(),
+                ))
+            }),
+            None => Ok(()),
+        };
+
+        match result {
+            Ok(()) => self
+                .status
+                .store(MIDIRendererStatus::Finished, Ordering::Relaxed),
+            Err(err) => {
+                error!("Error finalizing audio file: {}", err);
+                *self.error.write().unwrap() = Some(err.to_string());
+                self.status
+                    .store(MIDIRendererStatus::Error, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn run(&mut self, stats: Arc<RenderStatsAtomic>) {
+        // The progress is reported in MIDI time, which differs from the render time
+        // when the speed is changed
+        let speed = self.speed;
+        let update_stats = |time: f64, voices: u64| {
+            stats.time.store(time * speed, Ordering::Relaxed);
+            stats.voices.store(voices, Ordering::Relaxed);
+        };
+
+        for batch in self.receiver.clone() {
+            if !self.allow.load(Ordering::Relaxed) {
+                break;
+            }
+
+            if let Some(end) = self.end {
+                if self.time + batch.delta >= end {
+                    self.advance(end - self.time, update_stats);
+                    break;
+                }
+            }
+            self.advance(batch.delta, update_stats);
+
+            // Before the start, only the channel state is updated and no notes are played
+            let fast_forward = self.time < self.start;
+
+            for event in batch.iter_inner() {
+                let track = event.track as usize;
+                let instance = match &self.track_stems {
+                    Some(stems) => stems.get(track).copied().unwrap_or(0),
+                    None => 0,
+                };
+                // Events address the channels of the port their track is set to
+                let port = self.track_ports.get(track).copied().unwrap_or(0) as usize;
+                let channel = |channel: u8| port * CHANNELS_PER_PORT + channel as usize;
+                let channels = self.channel_settings.len();
+                let index =
+                    |ch: u8| instance_channel_to_index(instance, channel(ch), channels) as u32;
+                // Drum keys select the instrument, so the drum channels are not transposed
+                let (transpose, transpose_mode) = (self.transpose, self.transpose_mode);
+                let key = |ch: u8, key: u8| {
+                    if self.drums.is_drums(channel(ch)) {
+                        Some(key)
+                    } else {
+                        transpose_key(key, transpose, transpose_mode)
+                    }
+                };
+
+                // Nothing is played before the start, so nothing can lag there either
+                let allowed = match &mut self.note_dropper {
+                    Some(dropper) if !fast_forward => {
+                        dropper.allow(self.time, matches!(event.event, Event::NoteOn(..)))
+                    }
+                    _ => true,
+                };
+
+                match &event.event {
+                    Event::NoteOn(e) => {
+                        if fast_forward || !allowed || self.ignore_range.contains(&e.velocity) {
+                            continue;
+                        }
+                        // The ignore range applies to the velocity in the MIDI, before the curve
+                        let vel =
+                            self.velocity_tables[channel(e.channel)][e.velocity.min(127) as usize];
+                        if let Some(key) = key(e.channel, e.key) {
+                            let index = index(e.channel);
+                            // The drum bank is selected right before the notes that need it
+                            let bank = self.drums.sync(channel(e.channel), index as usize);
+                            if let Some(event) = bank {
+                                self.renderer.send_event(SynthEvent::Channel(
+                                    index,
+                                    ChannelAudioEvent::Control(event),
+                                ));
+                            }
+                            self.renderer.send_event(SynthEvent::Channel(
+                                index,
+                                ChannelAudioEvent::NoteOn { key, vel },
+                            ));
+                        }
+                    }
+                    Event::NoteOff(e) => {
+                        if let Some(key) = key(e.channel, e.key) {
+                            self.renderer.send_event(SynthEvent::Channel(
+                                index(e.channel),
+                                ChannelAudioEvent::NoteOff { key },
+                            ));
+                        }
+                    }
+                    Event::ControlChange(e) => {
+                        let index = index(e.channel);
+                        let event = match (e.controller, e.value) {
+                            (0x00, bank) => {
+                                self.drums
+                                    .bank_select(channel(e.channel), index as usize, bank)
+                            }
+                            (0x79, 0) => {
+                                self.drums.reset_channel(index as usize);
+                                self.parameters[index as usize].control(e.controller, e.value)
+                            }
+                            _ => self.parameters[index as usize].control(e.controller, e.value),
+                        };
+                        if let Some(event) = event {
+                            self.renderer.send_event(SynthEvent::Channel(
+                                index,
+                                ChannelAudioEvent::Control(event),
+                            ));
+                        }
+                    }
+                    Event::PitchWheelChange(e) => {
+                        let index = index(e.channel);
+                        let event = self.parameters[index as usize].pitch_wheel(e.pitch);
+                        self.renderer.send_event(SynthEvent::Channel(
+                            index,
+                            ChannelAudioEvent::Control(event),
+                        ));
+                    }
+                    Event::ProgramChange(e) => {
+                        self.renderer.send_event(SynthEvent::Channel(
+                            index(e.channel),
+                            ChannelAudioEvent::ProgramChange(e.program),
+                        ));
+                    }
+                    Event::ChannelPressure(e) => {
+                        if let Some(cc) = self.channel_settings[channel(e.channel)].aftertouch_cc {
+                            self.renderer.send_event(SynthEvent::Channel(
+                                index(e.channel),
+                                ChannelAudioEvent::Control(ControlEvent::Raw(cc, e.pressure)),
+                            ));
+                        }
+                    }
+                    Event::PolyphonicKeyPressure(e) => {
+                        let settings = &self.channel_settings[channel(e.channel)];
+                        if let (Some(cc), true) =
+                            (settings.aftertouch_cc, settings.map_poly_aftertouch)
+                        {
+                            self.renderer.send_event(SynthEvent::Channel(
+                                index(e.channel),
+                                ChannelAudioEvent::Control(ControlEvent::Raw(cc, e.velocity)),
+                            ));
+                        }
+                    }
+                    Event::SystemExclusiveMessage(e) => match parse_sysex(&e.data) {
+                        Some(SysExMessage::RhythmPart { channel: ch, drums }) => {
+                            info!(
+                                "Channel {} of port {} plays {}",
+                                ch + 1,
+                                port + 1,
+                                if drums { "drums" } else { "notes" }
+                            );
+                            self.drums.set(channel(ch), drums);
+                        }
+                        Some(reset) => {
+                            info!("Resetting channels for {:?}", reset);
+                            self.parameters
+                                .iter_mut()
+                                .for_each(ChannelParameters::reset);
+                            self.drums.reset();
+                            for event in [
+                                ChannelAudioEvent::AllNotesOff,
+                                ChannelAudioEvent::ResetControl,
+                                ChannelAudioEvent::ProgramChange(0),
+                            ] {
+                                self.renderer.send_event(SynthEvent::AllChannels(event));
+                            }
+                        }
+                        None => {}
+                    },
+                    Event::MIDIPort(e) => {
+                        let ports = channels / CHANNELS_PER_PORT;
+                        // Only happens above `MAX_PORTS`, which was reported by the scan
+                        if e.channel as usize >= ports {
+                            warn!(
+                                "Port {} is not supported, using port {}",
+                                e.channel,
+                                e.channel as usize % ports
+                            );
+                        }
+                        if let Some(port) = self.track_ports.get_mut(track) {
+                            *port = e.channel % ports as u8;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.renderer
+            .send_event(SynthEvent::AllChannels(ChannelAudioEvent::AllNotesOff));
+        self.renderer
+            .send_event(SynthEvent::AllChannels(ChannelAudioEvent::ResetControl));
+        self.finalize();
+    }
+}
+
+struct MIDIRendererContainer {
+    // MIDIs are only opened once their render starts, so a long queue does not hold
+    // all of them in memory
+    midi: Option<MIDIInput>,
+    stats: Arc<RenderStatsAtomic>,
+    status: Arc<Atomic<MIDIRendererStatus>>,
+    error: Arc<RwLock<Option<String>>>,
+    allow: Arc<AtomicBool>,
+}
+
+pub struct MIDIPool {
+    config: ForteConfig,
+    soundfonts: Arc<RwLock<HashMap<SoundfontKey, Arc<SampleSoundfont>>>>,
+    max_parallel: usize,
+    containers: Vec<MIDIRendererContainer>,
+}
+
+impl MIDIPool {
+    pub fn new(
+        config: &ForteConfig,
+        midis: Vec<MIDIInput>,
+        soundfonts: Arc<RwLock<HashMap<SoundfontKey, Arc<SampleSoundfont>>>>,
+    ) -> Result<Self, MIDIRendererError> {
+        info!("Creating new MIDI thread manager");
+        if midis.is_empty() {
+            error!("The MIDI list is empty. Aborting conversion.");
+            return Err(MIDIRendererError::Renderer("Empty MIDI List".to_owned()));
+        }
+
+        let containers = midis
+            .into_iter()
+            .map(|midi| MIDIRendererContainer {
+                midi: Some(midi),
+                stats: Arc::new(RenderStatsAtomic {
+                    time: Arc::new(AtomicF64::new(0.0)),
+                    voices: Arc::new(AtomicU64::new(0)),
+                }),
+                status: Arc::new(Atomic::new(MIDIRendererStatus::Idle)),
+                error: Arc::new(RwLock::new(None)),
+                allow: Arc::new(AtomicBool::new(true)),
+            })
+            .collect();
+
+        Ok(Self {
+            config: config.clone(),
+            soundfonts,
+            max_parallel: config.render_settings.parallel_midis,
+            containers,
+        })
+    }
+
+    pub fn run(&mut self) {
+        info!(
+            "Spawning {} renderers",
+            self.max_parallel.min(self.containers.len())
+        );
+        for _ in 0..self.max_parallel {
+            self.spawn_next();
+        }
+    }
+
+    pub fn spawn_next(&mut self) -> bool {
+        let active = self.get_active_len();
+
+        if active < self.max_parallel && active < self.containers.len() {
+            info!("Spawning the next renderer");
+            for container in &mut self.containers {
+                if container.status.load(Ordering::Relaxed) == MIDIRendererStatus::Idle {
+                    container
+                        .status
+                        .store(MIDIRendererStatus::Rendering, Ordering::Relaxed);
+                    let midi = container.midi.take();
+                    let config = self.config.clone();
+                    let soundfonts = self.soundfonts.clone();
+                    let cellcallback = container.stats.clone();
+                    let allow = container.allow.clone();
+                    let status = container.status.clone();
+                    let error = container.error.clone();
+                    thread::spawn(move || {
+                        let midi = match midi {
+                            Some(midi) if allow.load(Ordering::Relaxed) => midi,
+                            // Cancelled before it started
+                            _ => {
+                                status.store(MIDIRendererStatus::Finished, Ordering::Relaxed);
+                                return;
+                            }
+                        };
+
+                        let path = midi.path.clone();
+                        match MIDIRenderer::load_new(
+                            &config,
+                            midi,
+                            soundfonts,
+                            allow,
+                            status.clone(),
+                            error.clone(),
+                        ) {
+                            Ok(mut renderer) => {
+                                renderer.set_soundfonts(&config);
+                                renderer.run(cellcallback);
+                            }
+                            // The rest of the list is still rendered, the MIDI is only
+                            // marked as failed
+                            Err(err) => {
+                                error!("Unable to load {:?}: {}", path, err);
+                                *error.write().unwrap() = Some(err.to_string());
+                                status.store(MIDIRendererStatus::Error, Ordering::Relaxed);
+                            }
+                        }
+                    });
+                    return true;
+                }
+            }
+            false
+        } else {
+            false
+        }
+    }
+
+    fn get_active_len(&self) -> usize {
+        let mut active = 0;
+        for container in &self.containers {
+            if container.status.load(Ordering::Relaxed) == MIDIRendererStatus::Rendering {
+                active += 1;
+            }
+        }
+        active
+    }
+
+    pub fn status(&mut self) -> MIDIRendererStatus {
+        let mut status = MIDIRendererStatus::Finished;
+
+        for container in &self.containers {
+            status = container.status.load(Ordering::Relaxed);
+            if status == MIDIRendererStatus::Rendering {
+                break;
+            }
+        }
+
+        // A failed MIDI does not stop the rest of the queue, so it counts as done here
+        if status == MIDIRendererStatus::Error {
+            status = MIDIRendererStatus::Finished;
+        }
+
+        status
+    }
+
+    pub fn get_statuses(&self) -> Vec<MIDIRendererStatus> {
+        self.containers
+            .iter()
+            .map(|container| container.status.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Returns what went wrong for every MIDI that failed
+    pub fn get_errors(&self) -> Vec<Option<String>> {
+        self.containers
+            .iter()
+            .map(|container| container.error.read().unwrap().clone())
+            .collect()
+    }
+
+    pub fn get_stats(&self) -> Vec<Option<RenderStats>> {
+        let mut progress = Vec::new();
+
+        for container in &self.containers {
+            let status = container.status.load(Ordering::Relaxed) != MIDIRendererStatus::Idle;
+            let allow = container.allow.load(Ordering::Relaxed);
+            if status && allow {
+                progress.push(Some(RenderStats {
+                    time: container.stats.time.load(Ordering::Relaxed),
+                    voice_count: container.stats.voices.load(Ordering::Relaxed),
+                }))
+            } else {
+                progress.push(None);
+            }
+        }
+
+        progress
+    }
+
+    pub fn has_finished(&mut self) -> bool {
+        self.status() == MIDIRendererStatus::Finished
+    }
+
+    pub fn cancel(&mut self, id: usize) {
+        self.containers[id].allow.store(false, Ordering::Relaxed);
+    }
+
+    pub fn cancel_all(&mut self) {
+        for container in &self.containers {
+            container.allow.store(false, Ordering::Relaxed);
+        }
+        self.containers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A format 1 MIDI at 96 PPQ, the tracks are given without their end
+    fn midi(tracks: &[&[u8]]) -> MIDIFile<DiskReader> {
+        let mut file = b"MThd\0\0\0\x06\0\x01".to_vec();
+        file.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        file.extend_from_slice(&96u16.to_be_bytes());
+        for track in tracks {
+            let track = [track, &b"\0\xFF\x2F\0"[..]].concat();
+            file.extend_from_slice(b"MTrk");
+            file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            file.extend(track);
+        }
+        MIDIFile::open_from_stream(Cursor::new(file), None).unwrap()
+    }
+
+    fn used(channels: &[bool]) -> Vec<usize> {
+        (0..channels.len()).filter(|&i| channels[i]).collect()
+    }
+
+    #[test]
+    fn ports_set_after_the_first_note() {
+        let midi = midi(&[
+            b"\0\x90\x3C\x64\0\xFF\x21\x01\x02\0\x91\x3C\x64",
+            b"\0\x99\x24\x64",
+        ]);
+        let (ports, channels) = scan_ports(&midi);
+        assert_eq!(ports, 3);
+        assert_eq!(used(&channels), vec![0, 9, 2 * CHANNELS_PER_PORT + 1]);
+    }
+
+    #[test]
+    fn unsupported_ports_wrap_around() {
+        let midi = midi(&[b"\0\x90\x3C\x64\0\xFF\x21\x01\x21\0\x92\x3C\x64"]);
+        let (ports, channels) = scan_ports(&midi);
+        assert_eq!(ports, MAX_PORTS);
+        // Port 33 is played on port 1
+        assert_eq!(used(&channels), vec![0, CHANNELS_PER_PORT + 2]);
+    }
+
+    #[test]
+    fn one_stem_per_track_without_groups() {
+        let (stems, names) = track_stems(&[], 3).unwrap();
+        assert_eq!(stems, vec![0, 1, 2]);
+        assert_eq!(names, vec!["track01", "track02", "track03"]);
+    }
+
+    #[test]
+    fn ungrouped_tracks_share_a_stem() {
+        let (stems, names) = track_stems(&[vec![1, 3], vec![2]], 5).unwrap();
+        assert_eq!(stems, vec![0, 1, 0, 2, 2]);
+        assert_eq!(names, vec!["group01", "group02", "other"]);
+    }
+
+    #[test]
+    fn too_many_track_stems() {
+        assert!(track_stems(&[], MAX_TRACK_STEMS).is_ok());
+        assert!(track_stems(&[], MAX_TRACK_STEMS + 1).is_err());
+
+        // Grouped tracks don't count, only the stems they make
+        let groups = vec![(1..=1000).collect()];
+        assert!(track_stems(&groups, 1000).is_ok());
+        let groups: Vec<Vec<usize>> = (1..=MAX_TRACK_STEMS + 1).map(|t| vec![t]).collect();
+        assert!(track_stems(&groups, MAX_TRACK_STEMS + 1).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::settings::{NoteDropMode, NoteDropSettings};
+use std::collections::VecDeque;
+
+/// Decides which note ons a lagging live player would skip. Times are in seconds
+/// of the render.
+pub struct NoteDropper {
+    settings: NoteDropSettings,
+    buffer_secs: f64,
+
+    buffer_index: u64,
+    buffer_events: u32,
+    // The start times of the notes played in the last second
+    note_times: VecDeque<f64>,
+
+    dropped: u64,
+}
+
+impl NoteDropper {
+    pub fn new(settings: NoteDropSettings, buffer_ms: f32) -> Self {
+        Self {
+            settings,
+            buffer_secs: (buffer_ms as f64 / 1000.0).max(0.0001),
+            buffer_index: 0,
+            buffer_events: 0,
+            note_times: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Counts an event and returns false if it is a note on that is skipped
+    pub fn allow(&mut self, time: f64, note_on: bool) -> bool {
+        let allowed = match self.settings.mode {
+            NoteDropMode::Disabled => true,
+            NoteDropMode::EventsPerBuffer => {
+                let index = (time / self.buffer_secs) as u64;
+                if index != self.buffer_index {
+                    self.buffer_index = index;
+                    self.buffer_events = 0;
+                }
+
+                // Only note ons are skipped, every other event still takes up the buffer
+                if note_on && self.buffer_events >= self.settings.events_per_buffer {
+                    false
+                } else {
+                    self.buffer_events += 1;
+                    true
+                }
+            }
+            NoteDropMode::MaxNps => {
+                if !note_on {
+                    return true;
+                }
+                while let Some(&start) = self.note_times.front() {
+                    if time - start < 1.0 {
+                        break;
+                    }
+                    self.note_times.pop_front();
+                }
+
+                if self.note_times.len() >= self.settings.max_nps as usize {
+                    false
+                } else {
+                    self.note_times.push_back(time);
+                    true
+                }
+            }
+        };
+
+        if !allowed {
+            self.dropped += 1;
+        }
+        allowed
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dropper(mode: NoteDropMode, limit: u32) -> NoteDropper {
+        let settings = NoteDropSettings {
+            mode,
+            events_per_buffer: limit,
+            max_nps: limit,
+        };
+        NoteDropper::new(settings, 10.0)
+    }
+
+    #[test]
+    fn disabled_keeps_every_note() {
+        let mut dropper = dropper(NoteDropMode::Disabled, 0);
+        for _ in 0..100 {
+            assert!(dropper.allow(0.0, true));
+        }
+        assert_eq!(dropper.dropped(), 0);
+    }
+
+    #[test]
+    fn events_per_buffer() {
+        let mut dropper = dropper(NoteDropMode::EventsPerBuffer, 3);
+        assert!(dropper.allow(0.0, true));
+        assert!(dropper.allow(0.002, true));
+        // Other events take up the buffer too
+        assert!(dropper.allow(0.004, false));
+        assert!(!dropper.allow(0.006, true));
+        assert!(!dropper.allow(0.008, true));
+
+        // The next buffer starts empty
+        assert!(dropper.allow(0.01, true));
+        assert!(dropper.allow(0.012, true));
+        assert!(dropper.allow(0.014, true));
+        assert!(!dropper.allow(0.016, true));
+        assert_eq!(dropper.dropped(), 3);
+    }
+
+    #[test]
+    fn max_nps() {
+        let mut dropper = dropper(NoteDropMode::MaxNps, 2);
+        assert!(dropper.allow(0.0, true));
+        assert!(dropper.allow(0.5, true));
+        assert!(!dropper.allow(0.9, true));
+        // Only the notes of the last second count
+        assert!(dropper.allow(1.0, true));
+        assert!(!dropper.allow(1.4, true));
+        assert!(dropper.allow(1.5, true));
+        assert_eq!(dropper.dropped(), 2);
+    }
+
+    #[test]
+    fn note_offs_are_never_dropped() {
+        for mode in [NoteDropMode::EventsPerBuffer, NoteDropMode::MaxNps] {
+            let mut dropper = dropper(mode, 1);
+            assert!(dropper.allow(0.0, true));
+            assert!(!dropper.allow(0.0, true));
+            for _ in 0..100 {
+                assert!(dropper.allow(0.0, false));
+            }
+        }
+    }
+
+    #[test]
+    fn no_notes_are_left_hanging() {
+        for mode in [NoteDropMode::EventsPerBuffer, NoteDropMode::MaxNps] {
+            let mut dropper = dropper(mode, 50);
+            // The notes held on every key, as the synth sees them
+            let mut held = [0u32; 128];
+            let mut time = 0.0;
+            for i in 0..20000u32 {
+                let key = (i * 7 % 128) as usize;
+                // Every note ends a few notes after it starts
+                let off_key = ((i + 128 - 5) * 7 % 128) as usize;
+                time += 0.0001;
+
+                if dropper.allow(time, true) {
+                    held[key] += 1;
+                }
+                if i >= 5 && dropper.allow(time, false) {
+                    held[off_key] = held[off_key].saturating_sub(1);
+                }
+            }
+            for i in 20000 - 5..20000u32 {
+                let key = (i * 7 % 128) as usize;
+                assert!(dropper.allow(time, false));
+                held[key] = held[key].saturating_sub(1);
+            }
+
+            assert!(dropper.dropped() > 0);
+            assert_eq!(held.iter().sum::<u32>(), 0, "{mode}");
+        }
+    }
+}
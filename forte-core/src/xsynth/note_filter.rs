@@ -0,0 +1,444 @@
+use crate::settings::{NoteFilterSettings, CHANNELS_PER_PORT};
+use midi_toolkit::{
+    events::Event,
+    sequence::event::{Delta, Track},
+};
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Pending,
+    Keep,
+    Drop,
+}
+
+struct Slot {
+    time: f64,
+    event: Track<Event>,
+    decision: Decision,
+}
+
+// A note that has not ended yet
+struct ActiveNote {
+    id: u64,
+    start: f64,
+    dropped: bool,
+}
+
+/// Removes notes from a stream of events in ticks, together with their note offs.
+/// Events are held back until it is known whether the notes before them are kept,
+/// which is at most the minimum note length.
+pub struct NoteFilter<I, E>
+where
+    I: Iterator<Item = Result<Delta<f64, Track<Event>>, E>>,
+{
+    iter: Option<I>,
+    // Without any filter set up the events are passed on as they are, so the
+    // default settings do not pay for the lookahead
+    enabled: bool,
+    settings: NoteFilterSettings,
+    // The key range of every channel on every port
+    key_ranges: Vec<RangeInclusive<u8>>,
+    track_ports: HashMap<u32, u8>,
+
+    time: f64,
+    emitted_time: f64,
+    queue: VecDeque<Slot>,
+    // The id of the first slot in the queue, ids keep counting up as slots are added
+    first_id: u64,
+    // The notes that wait for their minimum length, in the order they started
+    undecided: VecDeque<(u64, f64)>,
+    // Note offs end the oldest note of their key and channel
+    active: HashMap<(usize, u8), VecDeque<ActiveNote>>,
+}
+
+pub fn filter_notes<I, E>(
+    iter: I,
+    settings: NoteFilterSettings,
+    key_ranges: Vec<RangeInclusive<u8>>,
+) -> NoteFilter<I, E>
+where
+    I: Iterator<Item = Result<Delta<f64, Track<Event>>, E>>,
+{
+    let enabled = settings.min_length.is_some()
+        || settings.remove_overlaps
+        || key_ranges.iter().any(|range| *range != (0..=127));
+
+    NoteFilter {
+        iter: Some(iter),
+        enabled,
+        settings,
+        key_ranges,
+        track_ports: HashMap::new(),
+        time: 0.0,
+        emitted_time: 0.0,
+        queue: VecDeque::new(),
+        first_id: 0,
+        undecided: VecDeque::new(),
+        active: HashMap::new(),
+    }
+}
+
+impl<I, E> NoteFilter<I, E>
+where
+    I: Iterator<Item = Result<Delta<f64, Track<Event>>, E>>,
+{
+    fn slot(&mut self, id: u64) -> Option<&mut Slot> {
+        id.checked_sub(self.first_id)
+            .and_then(|i| self.queue.get_mut(i as usize))
+    }
+
+    fn push(&mut self, event: Track<Event>, decision: Decision) -> u64 {
+        let id = self.first_id + self.queue.len() as u64;
+        self.queue.push_back(Slot {
+            time: self.time,
+            event,
+            decision,
+        });
+        id
+    }
+
+    fn channel(&self, track: u32, channel: u8) -> usize {
+        let port = self.track_ports.get(&track).copied().unwrap_or(0) as usize;
+        (port * CHANNELS_PER_PORT + channel as usize) % self.key_ranges.len()
+    }
+
+    fn add(&mut self, event: Track<Event>) {
+        // Notes that lasted long enough no longer hold back the events after them
+        if let Some(min_length) = self.settings.min_length {
+            while let Some(&(id, start)) = self.undecided.front() {
+                if self.time - start < min_length as f64 {
+                    break;
+                }
+                self.undecided.pop_front();
+                if let Some(slot) = self.slot(id) {
+                    if slot.decision == Decision::Pending {
+                        slot.decision = Decision::Keep;
+                    }
+                }
+            }
+        }
+
+        match &event.event {
+            Event::NoteOn(e) => {
+                let channel = self.channel(event.track, e.channel);
+                let overlaps = self
+                    .active
+                    .get(&(channel, e.key))
+                    .map(|notes| notes.iter().any(|n| !n.dropped))
+                    .unwrap_or(false);
+
+                let dropped = !self.key_ranges[channel].contains(&e.key)
+                    || (self.settings.remove_overlaps && overlaps);
+                let decision = if dropped {
+                    Decision::Drop
+                } else if self.settings.min_length.is_some() {
+                    Decision::Pending
+                } else {
+                    Decision::Keep
+                };
+
+                let key = e.key;
+                let id = self.push(event, decision);
+                if decision == Decision::Pending {
+                    self.undecided.push_back((id, self.time));
+                }
+                self.active
+                    .entry((channel, key))
+                    .or_default()
+                    .push_back(ActiveNote {
+                        id,
+                        start: self.time,
+                        dropped,
+                    });
+            }
+            Event::NoteOff(e) => {
+                let channel = self.channel(event.track, e.channel);
+                let note = self
+                    .active
+                    .get_mut(&(channel, e.key))
+                    .and_then(|notes| notes.pop_front());
+
+                let decision = match note {
+                    Some(note) if note.dropped => Decision::Drop,
+                    Some(note) => {
+                        let length = self.time - note.start;
+                        let min_length = self.settings.min_length.unwrap_or(0) as f64;
+                        match self.slot(note.id) {
+                            Some(slot) if slot.decision == Decision::Pending => {
+                                slot.decision = if length < min_length {
+                                    Decision::Drop
+                                } else {
+                                    Decision::Keep
+                                };
+                                slot.decision
+                            }
+                            _ => Decision::Keep,
+                        }
+                    }
+                    None => Decision::Keep,
+                };
+                self.push(event, decision);
+            }
+            Event::MIDIPort(e) => {
+                self.track_ports.insert(event.track, e.channel);
+                self.push(event, Decision::Keep);
+            }
+            _ => {
+                self.push(event, Decision::Keep);
+            }
+        }
+    }
+
+    // Notes that are still held at the end of the MIDI are kept
+    fn finish(&mut self) {
+        for slot in self.queue.iter_mut() {
+            if slot.decision == Decision::Pending {
+                slot.decision = Decision::Keep;
+            }
+        }
+        self.undecided.clear();
+    }
+}
+
+impl<I, E> Iterator for NoteFilter<I, E>
+where
+    I: Iterator<Item = Result<Delta<f64, Track<Event>>, E>>,
+{
+    type Item = Result<Delta<f64, Track<Event>>, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.enabled {
+            return self.iter.as_mut()?.next();
+        }
+
+        loop {
+            match self.queue.front().map(|slot| slot.decision) {
+                Some(Decision::Keep) => {
+                    let slot = self.queue.pop_front().unwrap();
+                    self.first_id += 1;
+                    let delta = slot.time - self.emitted_time;
+                    self.emitted_time = slot.time;
+                    return Some(Ok(Delta::new(delta, slot.event)));
+                }
+                Some(Decision::Drop) => {
+                    self.queue.pop_front();
+                    self.first_id += 1;
+                    continue;
+                }
+                Some(Decision::Pending) | None => {}
+            }
+
+            match self.iter.as_mut().and_then(|iter| iter.next()) {
+                Some(Ok(event)) => {
+                    self.time += event.delta;
+                    self.add(event.event);
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    if self.iter.take().is_none() && self.queue.is_empty() {
+                        return None;
+                    }
+                    self.finish();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midi_toolkit::events::{MIDIPortEvent, NoteOffEvent, NoteOnEvent};
+
+    type Item = Result<Delta<f64, Track<Event>>, ()>;
+
+    fn event(delta: f64, track: u32, event: Event) -> Item {
+        Ok(Delta::new(delta, Track::new(event, track)))
+    }
+
+    fn on(delta: f64, channel: u8, key: u8) -> Item {
+        let e = NoteOnEvent {
+            channel,
+            key,
+            velocity: 100,
+        };
+        event(delta, 0, Event::NoteOn(e))
+    }
+
+    fn off(delta: f64, channel: u8, key: u8) -> Item {
+        event(delta, 0, Event::NoteOff(NoteOffEvent { channel, key }))
+    }
+
+    fn full_range() -> Vec<RangeInclusive<u8>> {
+        vec![0..=127; CHANNELS_PER_PORT]
+    }
+
+    // Returns the time in ticks, the kind and the key of every event that is kept
+    fn filter(
+        events: Vec<Item>,
+        settings: NoteFilterSettings,
+        key_ranges: Vec<RangeInclusive<u8>>,
+    ) -> Vec<(f64, &'static str, u8)> {
+        let mut time = 0.0;
+        filter_notes(events.into_iter(), settings, key_ranges)
+            .map(|e| {
+                let e = e.unwrap();
+                time += e.delta;
+                match &e.event.event {
+                    Event::NoteOn(n) => (time, "on", n.key),
+                    Event::NoteOff(n) => (time, "off", n.key),
+                    _ => (time, "other", 0),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn passes_everything_by_default() {
+        let events = vec![
+            on(0.0, 0, 60),
+            on(5.0, 0, 60),
+            off(1.0, 0, 60),
+            off(0.0, 0, 60),
+        ];
+        assert_eq!(
+            filter(events, NoteFilterSettings::default(), full_range()),
+            vec![
+                (0.0, "on", 60),
+                (5.0, "on", 60),
+                (6.0, "off", 60),
+                (6.0, "off", 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn key_range() {
+        let mut ranges = full_range();
+        ranges[0] = 60..=72;
+
+        let events = vec![
+            on(0.0, 0, 50),
+            on(2.0, 0, 60),
+            on(0.0, 1, 50),
+            off(3.0, 0, 50),
+            off(1.0, 0, 60),
+            off(0.0, 1, 50),
+        ];
+        assert_eq!(
+            filter(events, NoteFilterSettings::default(), ranges),
+            vec![
+                (2.0, "on", 60),
+                (2.0, "on", 50),
+                (6.0, "off", 60),
+                (6.0, "off", 50)
+            ]
+        );
+    }
+
+    #[test]
+    fn key_range_follows_ports() {
+        let mut ranges = vec![0..=127; 2 * CHANNELS_PER_PORT];
+        ranges[CHANNELS_PER_PORT] = 0..=0;
+
+        let port = Event::MIDIPort(Box::new(MIDIPortEvent { channel: 1 }));
+        let events = vec![
+            on(0.0, 0, 60),
+            event(0.0, 1, port),
+            event(
+                0.0,
+                1,
+                Event::NoteOn(NoteOnEvent {
+                    channel: 0,
+                    key: 60,
+                    velocity: 100,
+                }),
+            ),
+            off(1.0, 0, 60),
+        ];
+        assert_eq!(
+            filter(events, NoteFilterSettings::default(), ranges),
+            vec![(0.0, "on", 60), (0.0, "other", 0), (1.0, "off", 60)]
+        );
+    }
+
+    #[test]
+    fn min_length() {
+        let settings = NoteFilterSettings {
+            min_length: Some(10),
+            ..Default::default()
+        };
+
+        let events = vec![
+            on(0.0, 0, 60),
+            on(0.0, 0, 62),
+            off(5.0, 0, 60),
+            off(5.0, 0, 62),
+            on(0.0, 0, 64),
+            on(20.0, 0, 66),
+        ];
+        assert_eq!(
+            filter(events, settings, full_range()),
+            vec![
+                (0.0, "on", 62),
+                (10.0, "off", 62),
+                (10.0, "on", 64),
+                (30.0, "on", 66)
+            ]
+        );
+    }
+
+    #[test]
+    fn min_length_keeps_order() {
+        let settings = NoteFilterSettings {
+            min_length: Some(10),
+            ..Default::default()
+        };
+
+        let events = vec![
+            on(0.0, 0, 60),
+            event(
+                2.0,
+                0,
+                Event::MIDIPort(Box::new(MIDIPortEvent { channel: 0 })),
+            ),
+            off(20.0, 0, 60),
+        ];
+        assert_eq!(
+            filter(events, settings, full_range()),
+            vec![(0.0, "on", 60), (2.0, "other", 0), (22.0, "off", 60)]
+        );
+    }
+
+    #[test]
+    fn remove_overlaps() {
+        let settings = NoteFilterSettings {
+            remove_overlaps: true,
+            ..Default::default()
+        };
+
+        let events = vec![
+            on(0.0, 0, 60),
+            on(5.0, 0, 60),
+            on(0.0, 1, 60),
+            off(3.0, 0, 60),
+            off(2.0, 0, 60),
+            off(0.0, 1, 60),
+            on(1.0, 0, 60),
+            off(1.0, 0, 60),
+        ];
+        assert_eq!(
+            filter(events, settings, full_range()),
+            vec![
+                (0.0, "on", 60),
+                (5.0, "on", 60),
+                (8.0, "off", 60),
+                (10.0, "off", 60),
+                (11.0, "on", 60),
+                (12.0, "off", 60)
+            ]
+        );
+    }
+}
@@ -1,10 +1,9 @@
-use super::midi_pool::{MIDIPool, MIDIRendererStatus};
+use super::midi_pool::{MIDIInput, MIDIPool};
 use super::soundfont_pool::{SoundfontPool, SoundfontWorkerStatus};
-use crate::elements::sf_list::ForteSFListItem;
+use super::MIDIRendererStatus;
 use crate::errors::error_types::MIDIRendererError;
-use crate::settings::ForteState;
+use crate::settings::{ForteConfig, ForteSFListItem};
 use std::collections::HashMap;
-use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use tracing::info;
 use xsynth_core::AudioStreamParams;
@@ -18,6 +17,7 @@ pub enum ManagerStatus {
     RenderFinished,
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct RenderStats {
     pub time: f64,
     pub voice_count: u64,
@@ -29,31 +29,30 @@ pub struct RenderThreadManager {
 }
 
 impl RenderThreadManager {
-    pub fn new(state: &ForteState, midis: Vec<PathBuf>) -> Result<Self, MIDIRendererError> {
+    pub fn new(config: &ForteConfig, midis: Vec<MIDIInput>) -> Result<Self, MIDIRendererError> {
         info!("Creating new render thread manager");
         let soundfonts = Arc::new(RwLock::new(HashMap::new()));
 
         let mut soundfonts_paths: Vec<ForteSFListItem> = vec![];
-        for channel in state.synth_settings.unify() {
+        for channel in config
+            .synth_settings
+            .unify(config.synth_settings.configured_ports())
+        {
             for sf in channel.soundfonts {
-                if !soundfonts_paths
-                    .clone()
-                    .into_iter()
-                    .any(|item| item.path == sf.path)
-                {
+                if !soundfonts_paths.iter().any(|item| item.key() == sf.key()) {
                     soundfonts_paths.push(sf);
                 }
             }
         }
 
         let audio_params = AudioStreamParams::new(
-            state.render_settings.sample_rate,
-            state.render_settings.audio_channels,
+            config.render_settings.sample_rate,
+            config.render_settings.audio_channels,
         );
 
         let soundfont_pool = SoundfontPool::new(soundfonts_paths, soundfonts.clone(), audio_params);
 
-        let midi_pool = MIDIPool::new(state, midis, soundfonts)?;
+        let midi_pool = MIDIPool::new(config, midis, soundfonts)?;
 
         Ok(Self {
             soundfont_pool,
@@ -77,8 +76,7 @@ impl RenderThreadManager {
         status
     }
 
-    pub fn render(&mut self, state: &ForteState) {
-        self.midi_pool.set_soundfonts(state);
+    pub fn render(&mut self) {
         self.midi_pool.run();
     }
 
@@ -90,6 +88,18 @@ impl RenderThreadManager {
         self.midi_pool.get_stats()
     }
 
+    pub fn get_statuses(&self) -> Vec<MIDIRendererStatus> {
+        self.midi_pool.get_statuses()
+    }
+
+    pub fn get_errors(&self) -> Vec<Option<String>> {
+        self.midi_pool.get_errors()
+    }
+
+    pub fn soundfont_error(&self) -> Option<String> {
+        self.soundfont_pool.error()
+    }
+
     pub fn has_finished(&mut self) -> bool {
         self.midi_pool.has_finished()
     }
@@ -0,0 +1,51 @@
+mod standard;
+pub use standard::*;
+
+mod buffered;
+pub use buffered::*;
+
+mod lazy_channel;
+use lazy_channel::LazyVoiceChannel;
+
+mod mixer;
+use mixer::ChannelMixer;
+
+use xsynth_core::channel::{ChannelAudioEvent, ChannelConfigEvent};
+use xsynth_core::AudioStreamParams;
+
+pub enum SynthEvent {
+    Channel(u32, ChannelAudioEvent),
+    AllChannels(ChannelAudioEvent),
+    ChannelConfig(u32, ChannelConfigEvent),
+}
+
+pub trait Renderer: Sync + Send {
+    fn stream_params(&self) -> &'_ AudioStreamParams;
+
+    fn send_event(&mut self, event: SynthEvent);
+
+    fn read_samples(&mut self, to: &mut [f32]) {
+        assert!(to.len() as u32 % self.stream_params().channels as u32 == 0);
+        self.read_samples_unchecked(to);
+    }
+
+    fn read_samples_unchecked(&mut self, to: &mut [f32]);
+
+    /// Same as `read_samples`, but also writes the output of the synth channels to
+    /// `stems`. The channels are split evenly between the stems in order, so with one
+    /// stem per channel every channel gets its own and with one stem per instance
+    /// every stem holds one instance. Each stem ends up with the length of `to`.
+    fn read_samples_with_stems(&mut self, to: &mut [f32], stems: &mut [Vec<f32>]) {
+        assert!(to.len() as u32 % self.stream_params().channels as u32 == 0);
+        self.read_samples_with_stems_unchecked(to, stems);
+    }
+
+    fn read_samples_with_stems_unchecked(&mut self, to: &mut [f32], stems: &mut [Vec<f32>]);
+
+    fn voice_count(&self) -> u64;
+}
+
+/// Every instance has its own set of `channels` synth channels, one after another
+pub fn instance_channel_to_index(instance: usize, channel: usize, channels: usize) -> usize {
+    instance * channels + channel
+}
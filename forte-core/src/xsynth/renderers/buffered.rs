@@ -0,0 +1,226 @@
+use super::{ChannelMixer, LazyVoiceChannel, Renderer, SynthEvent};
+use crate::settings::{ForteConfig, SingleChannelSettings, StemMode};
+use crossbeam_channel::Sender;
+use std::collections::VecDeque;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::thread;
+use tracing::info;
+use xsynth_core::buffered_renderer::BufferedRenderer;
+use xsynth_core::channel::{ChannelEvent, VoiceChannelStatsReader};
+use xsynth_core::helpers::{prepapre_cache_vec, sum_simd};
+use xsynth_core::{AudioPipe, AudioStreamParams, FunctionAudioPipe};
+
+// The channels that are running, shared with the audio pipe
+#[derive(Default)]
+struct ActiveChannels {
+    command_senders: Vec<(usize, Sender<Vec<f32>>)>,
+    stats: Vec<VoiceChannelStatsReader>,
+}
+
+pub struct ForteBufferedRenderer {
+    buffered: BufferedRenderer,
+    // Channels are only started when they receive their first audio event
+    channels: Vec<LazyVoiceChannel>,
+    mixers: Vec<Option<ChannelMixer>>,
+    senders: Vec<Option<Sender<ChannelEvent>>>,
+    active: Arc<Mutex<ActiveChannels>>,
+    output_sender: Sender<(usize, Vec<f32>)>,
+    voice_count: Arc<AtomicU64>,
+    stem_queues: Option<Arc<Mutex<Vec<VecDeque<f32>>>>>,
+    audio_params: AudioStreamParams,
+}
+
+impl ForteBufferedRenderer {
+    pub fn new(config: &ForteConfig, settings: &[SingleChannelSettings], instances: usize) -> Self {
+        info!(
+            "Creating new buffered renderer with {instances} instance(s) of {} channels",
+            settings.len()
+        );
+        let mut channels = Vec::new();
+        let mut mixers = Vec::new();
+        let mut senders = Vec::new();
+
+        let audio_params = AudioStreamParams::new(
+            config.render_settings.sample_rate,
+            config.render_settings.audio_channels,
+        );
+
+        let (output_sender, output_receiver) = crossbeam_channel::bounded::<(usize, Vec<f32>)>(16);
+
+        let channel_count = settings.len() * instances;
+        // The buffered renderer only returns the mix, so the output of every channel is
+        // queued separately and read back in the same order as the mixed samples
+        let stem_queues = if config.render_settings.stem_mode != StemMode::Disabled {
+            Some(Arc::new(Mutex::new(vec![VecDeque::new(); channel_count])))
+        } else {
+            None
+        };
+
+        for _ in 0..instances {
+            mixers.extend(
+                ChannelMixer::for_channels(settings, audio_params)
+                    .into_iter()
+                    .map(Some),
+            );
+            for ch in settings.iter() {
+                channels.push(LazyVoiceChannel::new(ch, audio_params));
+                senders.push(None);
+            }
+        }
+
+        let active = Arc::new(Mutex::new(ActiveChannels::default()));
+
+        let mut vec_cache: VecDeque<Vec<f32>> = VecDeque::new();
+
+        let voice_count = Arc::new(AtomicU64::new(0));
+        let voice_countc = voice_count.clone();
+        let stem_queuesc = stem_queues.clone();
+        let activec = active.clone();
+
+        let fnpipe = FunctionAudioPipe::new(audio_params, move |out| {
+            let active = activec.lock().unwrap();
+
+            for (_, sender) in active.command_senders.iter() {
+                let mut buf = vec_cache.pop_front().unwrap_or_default();
+                prepapre_cache_vec(&mut buf, out.len(), 0.0);
+
+                sender.send(buf).unwrap();
+            }
+
+            for _ in 0..active.command_senders.len() {
+                let (index, buf) = output_receiver.recv().unwrap();
+                sum_simd(&buf, out);
+                if let Some(queues) = &stem_queuesc {
+                    queues.lock().unwrap()[index].extend(buf.iter());
+                }
+                vec_cache.push_front(buf);
+            }
+
+            // Channels that are not running still have to keep their stems in sync
+            if let Some(queues) = &stem_queuesc {
+                let mut queues = queues.lock().unwrap();
+                let mut running = active.command_senders.iter().map(|(i, _)| *i).peekable();
+                for (index, queue) in queues.iter_mut().enumerate() {
+                    if running.peek() == Some(&index) {
+                        running.next();
+                    } else {
+                        queue.extend(std::iter::repeat(0.0).take(out.len()));
+                    }
+                }
+            }
+
+            let total_voices = active.stats.iter().map(|c| c.voice_count()).sum();
+            voice_countc.store(total_voices, Ordering::SeqCst);
+        });
+
+        let buffered = BufferedRenderer::new(
+            fnpipe,
+            audio_params,
+            (audio_params.sample_rate as f32 * config.render_settings.realtime_buffer_ms / 1000.0)
+                as usize,
+        );
+
+        Self {
+            buffered,
+            channels,
+            mixers,
+            senders,
+            active,
+            output_sender,
+            voice_count,
+            stem_queues,
+            audio_params,
+        }
+    }
+
+    fn start_channel(&mut self, index: usize) -> &Sender<ChannelEvent> {
+        if self.senders[index].is_none() {
+            let mut channel = self.channels[index].create();
+            let mut mixer = self.mixers[index].take().unwrap();
+
+            let (event_sender, event_receiver) = crossbeam_channel::unbounded();
+            let (command_sender, command_receiver) = crossbeam_channel::bounded::<Vec<f32>>(1);
+
+            let mut active = self.active.lock().unwrap();
+            active.stats.push(channel.get_channel_stats());
+            // Kept in index order, so the stems can tell which channels are silent
+            let pos = active.command_senders.partition_point(|(i, _)| *i < index);
+            active.command_senders.insert(pos, (index, command_sender));
+
+            let output_sender = self.output_sender.clone();
+            thread::spawn(move || loop {
+                channel.push_events_iter(event_receiver.try_iter());
+                let mut vec = match command_receiver.recv() {
+                    Ok(vec) => vec,
+                    Err(_) => break,
+                };
+                channel.push_events_iter(event_receiver.try_iter());
+                channel.read_samples(&mut vec);
+                mixer.process(&mut vec);
+                output_sender.send((index, vec)).unwrap();
+            });
+
+            self.senders[index] = Some(event_sender);
+        }
+        self.senders[index].as_ref().unwrap()
+    }
+}
+
+impl Renderer for ForteBufferedRenderer {
+    fn stream_params(&self) -> &AudioStreamParams {
+        &self.audio_params
+    }
+
+    fn send_event(&mut self, event: SynthEvent) {
+        match event {
+            SynthEvent::Channel(channel, event) => {
+                self.start_channel(channel as usize)
+                    .send(ChannelEvent::Audio(event))
+                    .unwrap_or_default();
+            }
+            SynthEvent::AllChannels(event) => {
+                for sender in self.senders.iter().flatten() {
+                    sender
+                        .send(ChannelEvent::Audio(event.clone()))
+                        .unwrap_or_default();
+                }
+            }
+            SynthEvent::ChannelConfig(channel, config) => match &self.senders[channel as usize] {
+                Some(sender) => sender
+                    .send(ChannelEvent::Config(config))
+                    .unwrap_or_default(),
+                None => self.channels[channel as usize].process_config(config),
+            },
+        }
+    }
+
+    fn read_samples_unchecked(&mut self, to: &mut [f32]) {
+        self.buffered.read(to);
+    }
+
+    fn read_samples_with_stems_unchecked(&mut self, to: &mut [f32], stems: &mut [Vec<f32>]) {
+        self.buffered.read(to);
+
+        if let Some(queues) = &self.stem_queues {
+            let mut queues = queues.lock().unwrap();
+            let per_stem = queues.len() / stems.len();
+            for (stem, queues) in stems.iter_mut().zip(queues.chunks_mut(per_stem)) {
+                stem.clear();
+                stem.resize(to.len(), 0.0);
+                for queue in queues {
+                    let len = to.len().min(queue.len());
+                    for (s, q) in stem.iter_mut().zip(queue.drain(..len)) {
+                        *s += q;
+                    }
+                }
+            }
+        }
+    }
+
+    fn voice_count(&self) -> u64 {
+        self.voice_count.load(Ordering::Relaxed)
+    }
+}
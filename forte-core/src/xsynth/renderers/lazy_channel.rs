@@ -0,0 +1,68 @@
+use crate::settings::SingleChannelSettings;
+use std::sync::Arc;
+use xsynth_core::channel::{ChannelConfigEvent, ChannelEvent, ChannelInitOptions, VoiceChannel};
+use xsynth_core::AudioStreamParams;
+
+/// A voice channel that is only created when it is first used. Files can address
+/// hundreds of channels through MIDI ports, but usually only use a few of them.
+pub struct LazyVoiceChannel {
+    channel: Option<VoiceChannel>,
+    options: ChannelInitOptions,
+    use_threadpool: bool,
+    audio_params: AudioStreamParams,
+    // Config events received before the channel existed, applied when it is created
+    pending_config: Vec<ChannelConfigEvent>,
+}
+
+impl LazyVoiceChannel {
+    pub fn new(settings: &SingleChannelSettings, audio_params: AudioStreamParams) -> Self {
+        Self {
+            channel: None,
+            options: settings.channel_init_options,
+            use_threadpool: settings.use_threadpool,
+            audio_params,
+            pending_config: Vec::new(),
+        }
+    }
+
+    pub fn is_created(&self) -> bool {
+        self.channel.is_some()
+    }
+
+    pub fn get(&mut self) -> Option<&mut VoiceChannel> {
+        self.channel.as_mut()
+    }
+
+    pub fn get_ref(&self) -> Option<&VoiceChannel> {
+        self.channel.as_ref()
+    }
+
+    pub fn get_or_create(&mut self) -> &mut VoiceChannel {
+        if self.channel.is_none() {
+            self.channel = Some(self.create());
+        }
+        self.channel.as_mut().unwrap()
+    }
+
+    /// Creates the channel without storing it, for renderers that move it to its own thread
+    pub fn create(&mut self) -> VoiceChannel {
+        let pool = if self.use_threadpool {
+            Some(Arc::new(rayon::ThreadPoolBuilder::new().build().unwrap()))
+        } else {
+            None
+        };
+
+        let mut channel = VoiceChannel::new(self.options, self.audio_params, pool);
+        for config in self.pending_config.drain(..) {
+            channel.process_event(ChannelEvent::Config(config));
+        }
+        channel
+    }
+
+    pub fn process_config(&mut self, config: ChannelConfigEvent) {
+        match self.channel.as_mut() {
+            Some(channel) => channel.process_event(ChannelEvent::Config(config)),
+            None => self.pending_config.push(config),
+        }
+    }
+}
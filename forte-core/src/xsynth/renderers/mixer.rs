@@ -0,0 +1,84 @@
+use crate::dsp::{db_to_gain, EffectChain};
+use crate::settings::{ChannelMixerSettings, SingleChannelSettings};
+use std::f32::consts::FRAC_PI_4;
+use xsynth_core::AudioStreamParams;
+
+/// Applies the mixer settings of one synth channel to its output
+pub struct ChannelMixer {
+    gains: [f32; 2],
+    stereo: bool,
+    effects: EffectChain,
+}
+
+impl ChannelMixer {
+    /// `solo_active` tells whether any channel is soloed, in which case the channels
+    /// that are not soloed are silenced
+    pub fn new(
+        settings: &ChannelMixerSettings,
+        solo_active: bool,
+        audio_params: AudioStreamParams,
+    ) -> Self {
+        let audible = !settings.mute && (settings.solo || !solo_active);
+        let gain = if audible {
+            db_to_gain(settings.gain_db)
+        } else {
+            0.0
+        };
+
+        // Constant power panning, scaled so the center position keeps unity gain
+        let angle = (settings.pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+        let stereo = audio_params.channels.count() == 2;
+        let gains = if stereo {
+            [
+                gain * angle.cos() * 2f32.sqrt(),
+                gain * angle.sin() * 2f32.sqrt(),
+            ]
+        } else {
+            [gain, gain]
+        };
+
+        Self {
+            gains,
+            stereo,
+            effects: EffectChain::new(
+                &settings.effects,
+                audio_params.channels.count(),
+                audio_params.sample_rate,
+            ),
+        }
+    }
+
+    pub fn for_channels(
+        settings: &[SingleChannelSettings],
+        audio_params: AudioStreamParams,
+    ) -> Vec<Self> {
+        let solo_active = settings.iter().any(|ch| ch.mixer.solo);
+        settings
+            .iter()
+            .map(|ch| Self::new(&ch.mixer, solo_active, audio_params))
+            .collect()
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.gains == [0.0, 0.0] {
+            samples.fill(0.0);
+            return;
+        }
+
+        self.effects.process(samples);
+
+        if self.gains == [1.0, 1.0] {
+            return;
+        }
+        if self.stereo {
+            for frame in samples.chunks_exact_mut(2) {
+                frame[0] *= self.gains[0];
+                frame[1] *= self.gains[1];
+            }
+        } else {
+            for s in samples.iter_mut() {
+                *s *= self.gains[0];
+            }
+        }
+    }
+}
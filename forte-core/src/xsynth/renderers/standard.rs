@@ -1,9 +1,8 @@
-use super::{Renderer, SynthEvent};
-use crate::settings::ForteState;
+use super::{ChannelMixer, LazyVoiceChannel, Renderer, SynthEvent};
+use crate::settings::{ForteConfig, SingleChannelSettings};
 use rayon::prelude::*;
-use std::sync::Arc;
 use tracing::info;
-use xsynth_core::channel::{ChannelAudioEvent, ChannelEvent, VoiceChannel};
+use xsynth_core::channel::{ChannelAudioEvent, ChannelEvent};
 use xsynth_core::helpers::sum_simd;
 use xsynth_core::{AudioPipe, AudioStreamParams};
 
@@ -14,36 +13,32 @@ pub struct ForteStandardRenderer {
     cached_event_count: u32,
     channel_events_cache: Box<[Vec<ChannelAudioEvent>]>,
     sample_cache_vecs: Box<[Vec<f32>]>,
-    channels: Box<[VoiceChannel]>,
+    channels: Box<[LazyVoiceChannel]>,
+    mixers: Box<[ChannelMixer]>,
     audio_params: AudioStreamParams,
 }
 
 impl ForteStandardRenderer {
-    pub fn new(state: &ForteState, instances: usize) -> Self {
-        info!("Creating new renderer with {instances} instance(s)");
+    /// `settings` holds the settings of every synth channel of one instance
+    pub fn new(config: &ForteConfig, settings: &[SingleChannelSettings], instances: usize) -> Self {
+        info!(
+            "Creating new renderer with {instances} instance(s) of {} channels",
+            settings.len()
+        );
         let mut channels = Vec::new();
         let mut channel_events_cache = Vec::new();
         let mut sample_cache_vecs = Vec::new();
+        let mut mixers = Vec::new();
 
         let audio_params = AudioStreamParams::new(
-            state.render_settings.sample_rate,
-            state.render_settings.audio_channels,
+            config.render_settings.sample_rate,
+            config.render_settings.audio_channels,
         );
 
         for _ in 0..instances {
-            for (i, ch) in state.synth_settings.unify().into_iter().enumerate() {
-                let pool = if ch.use_threadpool {
-                    Some(Arc::new(rayon::ThreadPoolBuilder::new().build().unwrap()))
-                } else {
-                    None
-                };
-
-                let mut options = ch.channel_init_options;
-                if i == 9 {
-                    options.drums_only = true;
-                }
-
-                channels.push(VoiceChannel::new(options, audio_params, pool.clone()));
+            mixers.extend(ChannelMixer::for_channels(settings, audio_params));
+            for ch in settings.iter() {
+                channels.push(LazyVoiceChannel::new(ch, audio_params));
                 channel_events_cache.push(Vec::new());
                 sample_cache_vecs.push(Vec::new());
             }
@@ -56,6 +51,7 @@ impl ForteStandardRenderer {
             cached_event_count: 0,
             channel_events_cache: channel_events_cache.into_boxed_slice(),
             channels: channels.into_boxed_slice(),
+            mixers: mixers.into_boxed_slice(),
             sample_cache_vecs: sample_cache_vecs.into_boxed_slice(),
             audio_params,
         }
@@ -74,32 +70,56 @@ impl ForteStandardRenderer {
             channels
                 .par_iter_mut()
                 .zip(channel_events_cache.par_iter_mut())
+                .filter(|(_, events)| !events.is_empty())
                 .for_each(|(channel, events)| {
-                    channel.push_events_iter(events.drain(..).map(ChannelEvent::Audio));
+                    channel
+                        .get_or_create()
+                        .push_events_iter(events.drain(..).map(ChannelEvent::Audio));
                 });
         });
 
         self.cached_event_count = 0;
     }
 
-    fn render_to(&mut self, buffer: &mut [f32]) {
+    fn render_to(&mut self, buffer: &mut [f32], mut stems: Option<&mut [Vec<f32>]>) {
         self.flush_events();
 
         let thread_pool = &mut self.thread_pool;
         let channels = &mut self.channels;
+        let mixers = &mut self.mixers;
         let sample_cache_vecs = &mut self.sample_cache_vecs;
 
         thread_pool.install(move || {
             channels
                 .par_iter_mut()
+                .zip(mixers.par_iter_mut())
                 .zip(sample_cache_vecs.par_iter_mut())
-                .for_each(|(channel, samples)| {
-                    samples.resize(buffer.len(), 0.0);
-                    channel.read_samples(samples.as_mut_slice());
+                .for_each(|((channel, mixer), samples)| {
+                    if let Some(channel) = channel.get() {
+                        samples.resize(buffer.len(), 0.0);
+                        channel.read_samples(samples.as_mut_slice());
+                        mixer.process(samples);
+                    }
                 });
 
-            for vec in sample_cache_vecs.iter_mut() {
+            if let Some(stems) = stems.as_mut() {
+                for stem in stems.iter_mut() {
+                    stem.clear();
+                    stem.resize(buffer.len(), 0.0);
+                }
+            }
+
+            let channel_count = sample_cache_vecs.len();
+            // Channels that were never created did not write anything
+            for (i, vec) in sample_cache_vecs.iter_mut().enumerate() {
+                if vec.is_empty() {
+                    continue;
+                }
                 sum_simd(vec, buffer);
+                if let Some(stems) = stems.as_mut() {
+                    let per_stem = channel_count / stems.len();
+                    sum_simd(vec, &mut stems[i / per_stem]);
+                }
                 vec.clear();
             }
         });
@@ -121,27 +141,39 @@ impl Renderer for ForteStandardRenderer {
                 }
             }
             SynthEvent::AllChannels(event) => {
-                for channel in self.channel_events_cache.iter_mut() {
-                    channel.push(event.clone());
+                // Unused channels are not created just to receive the event
+                for (channel, events) in self
+                    .channels
+                    .iter()
+                    .zip(self.channel_events_cache.iter_mut())
+                {
+                    if channel.is_created() || !events.is_empty() {
+                        events.push(event.clone());
+                        self.cached_event_count += 1;
+                    }
                 }
-                self.cached_event_count += self.channel_events_cache.len() as u32;
                 if self.cached_event_count > MAX_EVENT_CACHE_SIZE {
                     self.flush_events();
                 }
             }
             SynthEvent::ChannelConfig(channel, config) => {
-                self.channels[channel as usize].process_event(ChannelEvent::Config(config));
+                self.channels[channel as usize].process_config(config);
             }
         }
     }
 
     fn read_samples_unchecked(&mut self, to: &mut [f32]) {
-        self.render_to(to);
+        self.render_to(to, None);
+    }
+
+    fn read_samples_with_stems_unchecked(&mut self, to: &mut [f32], stems: &mut [Vec<f32>]) {
+        self.render_to(to, Some(stems));
     }
 
     fn voice_count(&self) -> u64 {
         self.channels
             .iter()
+            .filter_map(|c| c.get_ref())
             .map(|c| c.get_channel_stats().voice_count())
             .sum()
     }
@@ -0,0 +1,306 @@
+use xsynth_core::channel::ControlEvent;
+
+// The parameter that data entry controllers currently change
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Parameter {
+    None,
+    Rpn(u8, u8),
+    Nrpn(u8, u8),
+}
+
+/// Follows the RPN and NRPN selection of one MIDI channel and turns data entry into
+/// the matching xsynth control events. xsynth only knows the RPN numbers and applies
+/// NRPN data to them too, so the parameter controllers never reach it directly.
+pub struct ChannelParameters {
+    default_bend_range: f32,
+
+    rpn: (Option<u8>, Option<u8>),
+    nrpn: (Option<u8>, Option<u8>),
+    selected: Parameter,
+
+    // Semitones and cents
+    bend_range: (u8, u8),
+    bend_value: f32,
+    fine_tune: (u8, u8),
+}
+
+impl ChannelParameters {
+    /// `default_bend_range` is the pitch bend range in semitones before any RPN sets it
+    pub fn new(default_bend_range: f32) -> Self {
+        let semitones = default_bend_range.clamp(0.0, 127.0);
+        Self {
+            default_bend_range,
+            rpn: (None, None),
+            nrpn: (None, None),
+            selected: Parameter::None,
+            bend_range: (semitones as u8, (semitones.fract() * 100.0).round() as u8),
+            bend_value: 0.0,
+            fine_tune: (64, 0),
+        }
+    }
+
+    /// Resets the channel the same way "reset all controllers" resets an xsynth channel
+    pub fn reset(&mut self) {
+        *self = Self::new(self.default_bend_range);
+    }
+
+    fn bend_range(&self) -> f32 {
+        self.bend_range.0 as f32 + self.bend_range.1 as f32 / 100.0
+    }
+
+    fn pitch_bend(&self) -> ControlEvent {
+        ControlEvent::PitchBend(self.bend_value * self.bend_range())
+    }
+
+    /// Returns the event for a pitch wheel change, between -8192 and 8191
+    pub fn pitch_wheel(&mut self, pitch: i16) -> ControlEvent {
+        self.bend_value = pitch as f32 / 8192.0;
+        self.pitch_bend()
+    }
+
+    /// Returns the event to send for a control change, or `None` if the controller
+    /// only changes the parameter state
+    pub fn control(&mut self, controller: u8, value: u8) -> Option<ControlEvent> {
+        match controller {
+            0x65 => self.select_rpn((Some(value), self.rpn.1)),
+            0x64 => self.select_rpn((self.rpn.0, Some(value))),
+            0x63 => self.select_nrpn((Some(value), self.nrpn.1)),
+            0x62 => self.select_nrpn((self.nrpn.0, Some(value))),
+            0x06 => self.data_entry(Some(value), None),
+            0x26 => self.data_entry(None, Some(value)),
+            0x60 => self.data_step(1),
+            0x61 => self.data_step(-1),
+            0x79 if value == 0 => {
+                self.reset();
+                Some(ControlEvent::Raw(controller, value))
+            }
+            _ => Some(ControlEvent::Raw(controller, value)),
+        }
+    }
+
+    fn select_rpn(&mut self, rpn: (Option<u8>, Option<u8>)) -> Option<ControlEvent> {
+        self.rpn = rpn;
+        self.selected = match rpn {
+            // The null RPN deselects the parameter
+            (Some(0x7F), Some(0x7F)) => Parameter::None,
+            (Some(msb), Some(lsb)) => Parameter::Rpn(msb, lsb),
+            _ => Parameter::None,
+        };
+        None
+    }
+
+    fn select_nrpn(&mut self, nrpn: (Option<u8>, Option<u8>)) -> Option<ControlEvent> {
+        self.nrpn = nrpn;
+        self.selected = match nrpn {
+            (Some(0x7F), Some(0x7F)) => Parameter::None,
+            (Some(msb), Some(lsb)) => Parameter::Nrpn(msb, lsb),
+            _ => Parameter::None,
+        };
+        None
+    }
+
+    fn data_entry(&mut self, msb: Option<u8>, lsb: Option<u8>) -> Option<ControlEvent> {
+        match self.selected {
+            Parameter::Rpn(0, 0) => {
+                self.bend_range = (msb.unwrap_or(self.bend_range.0), lsb.unwrap_or(0));
+                Some(self.pitch_bend())
+            }
+            Parameter::Rpn(0, 1) => {
+                self.fine_tune = (msb.unwrap_or(self.fine_tune.0), lsb.unwrap_or(0));
+                let value = ((self.fine_tune.0 as u16) << 7) | self.fine_tune.1 as u16;
+                Some(ControlEvent::FineTune(
+                    (value as f32 - 8192.0) / 8192.0 * 100.0,
+                ))
+            }
+            Parameter::Rpn(0, 2) => msb.map(|msb| ControlEvent::CoarseTune(msb as f32 - 64.0)),
+            // The GS and XG sound controllers have the same effect as their CCs
+            Parameter::Nrpn(1, lsb_param) => {
+                let cc = match lsb_param {
+                    0x20 => 0x4A,
+                    0x21 => 0x47,
+                    0x63 => 0x49,
+                    0x66 => 0x48,
+                    _ => return None,
+                };
+                msb.map(|msb| ControlEvent::Raw(cc, msb))
+            }
+            _ => None,
+        }
+    }
+
+    fn data_step(&mut self, step: i8) -> Option<ControlEvent> {
+        let msb = match self.selected {
+            Parameter::Rpn(0, 0) => self.bend_range.0,
+            Parameter::Rpn(0, 1) => self.fine_tune.0,
+            _ => return None,
+        };
+        let msb = msb.saturating_add_signed(step).min(0x7F);
+        self.data_entry(Some(msb), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_PITCH: i16 = -8192;
+
+    fn bend(event: Option<ControlEvent>) -> f32 {
+        match event {
+            Some(ControlEvent::PitchBend(semitones)) => semitones,
+            other => panic!("Expected a pitch bend, got {other:?}"),
+        }
+    }
+
+    fn select_rpn(params: &mut ChannelParameters, msb: u8, lsb: u8) {
+        assert!(params.control(0x65, msb).is_none());
+        assert!(params.control(0x64, lsb).is_none());
+    }
+
+    fn select_nrpn(params: &mut ChannelParameters, msb: u8, lsb: u8) {
+        assert!(params.control(0x63, msb).is_none());
+        assert!(params.control(0x62, lsb).is_none());
+    }
+
+    #[test]
+    fn default_bend_range() {
+        let mut params = ChannelParameters::new(2.0);
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -2.0);
+
+        let mut params = ChannelParameters::new(12.5);
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -12.5);
+    }
+
+    #[test]
+    fn rpn_sets_bend_range() {
+        let mut params = ChannelParameters::new(2.0);
+        params.pitch_wheel(MIN_PITCH);
+
+        select_rpn(&mut params, 0, 0);
+        // The current bend is sent again with the new range
+        assert_eq!(bend(params.control(0x06, 12)), -12.0);
+        assert_eq!(bend(params.control(0x26, 50)), -12.5);
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -12.5);
+    }
+
+    #[test]
+    fn data_entry_without_rpn_is_ignored() {
+        let mut params = ChannelParameters::new(2.0);
+        assert!(params.control(0x06, 24).is_none());
+        assert!(params.control(0x26, 10).is_none());
+        assert!(params.control(0x60, 0).is_none());
+
+        // Only the LSB of the parameter number is not a full selection
+        assert!(params.control(0x64, 0).is_none());
+        assert!(params.control(0x06, 24).is_none());
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -2.0);
+    }
+
+    #[test]
+    fn null_rpn_deselects() {
+        let mut params = ChannelParameters::new(2.0);
+        select_rpn(&mut params, 0, 0);
+        bend(params.control(0x06, 4));
+
+        select_rpn(&mut params, 0x7F, 0x7F);
+        assert!(params.control(0x06, 24).is_none());
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -4.0);
+    }
+
+    #[test]
+    fn tuning() {
+        let mut params = ChannelParameters::new(2.0);
+
+        select_rpn(&mut params, 0, 1);
+        assert!(matches!(
+            params.control(0x06, 0x40),
+            Some(ControlEvent::FineTune(cents)) if cents == 0.0
+        ));
+        assert!(matches!(
+            params.control(0x06, 0),
+            Some(ControlEvent::FineTune(cents)) if cents == -100.0
+        ));
+
+        select_rpn(&mut params, 0, 2);
+        assert!(matches!(
+            params.control(0x06, 66),
+            Some(ControlEvent::CoarseTune(semitones)) if semitones == 2.0
+        ));
+        // Coarse tuning has no LSB
+        assert!(params.control(0x26, 10).is_none());
+    }
+
+    #[test]
+    fn data_increment_and_decrement() {
+        let mut params = ChannelParameters::new(2.0);
+        params.pitch_wheel(MIN_PITCH);
+        select_rpn(&mut params, 0, 0);
+
+        assert_eq!(bend(params.control(0x60, 0)), -3.0);
+        assert_eq!(bend(params.control(0x61, 0)), -2.0);
+
+        bend(params.control(0x06, 0));
+        assert_eq!(bend(params.control(0x61, 0)), 0.0);
+
+        bend(params.control(0x06, 0x7F));
+        bend(params.control(0x60, 0));
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -127.0);
+    }
+
+    #[test]
+    fn nrpn_maps_sound_controllers() {
+        let mut params = ChannelParameters::new(2.0);
+        select_nrpn(&mut params, 1, 0x20);
+        assert!(matches!(
+            params.control(0x06, 90),
+            Some(ControlEvent::Raw(0x4A, 90))
+        ));
+
+        select_nrpn(&mut params, 1, 0x66);
+        assert!(matches!(
+            params.control(0x06, 10),
+            Some(ControlEvent::Raw(0x48, 10))
+        ));
+
+        // Unknown NRPNs and RPN numbers selected through NRPN change nothing
+        select_nrpn(&mut params, 0x7E, 0);
+        assert!(params.control(0x06, 10).is_none());
+        select_nrpn(&mut params, 0, 0);
+        assert!(params.control(0x06, 24).is_none());
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -2.0);
+    }
+
+    #[test]
+    fn reset_all_controllers() {
+        let mut params = ChannelParameters::new(2.0);
+        select_rpn(&mut params, 0, 0);
+        bend(params.control(0x06, 12));
+        params.pitch_wheel(MIN_PITCH);
+
+        // xsynth resets its pitch state on CC121, so the range goes back to the default
+        assert!(matches!(
+            params.control(0x79, 0),
+            Some(ControlEvent::Raw(0x79, 0))
+        ));
+        assert!(params.control(0x06, 24).is_none());
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -2.0);
+
+        // Only a value of 0 resets the controllers
+        select_rpn(&mut params, 0, 0);
+        bend(params.control(0x06, 12));
+        assert!(matches!(
+            params.control(0x79, 1),
+            Some(ControlEvent::Raw(0x79, 1))
+        ));
+        assert_eq!(bend(Some(params.pitch_wheel(MIN_PITCH))), -12.0);
+    }
+
+    #[test]
+    fn other_controllers_pass_through() {
+        let mut params = ChannelParameters::new(2.0);
+        assert!(matches!(
+            params.control(0x07, 100),
+            Some(ControlEvent::Raw(0x07, 100))
+        ));
+    }
+}
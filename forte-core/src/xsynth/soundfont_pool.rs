@@ -0,0 +1,272 @@
+use crate::settings::{ForteSFListItem, SoundfontKey};
+use atomic::Atomic;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{atomic::AtomicBool, Arc, RwLock};
+use std::thread;
+use tracing::{error, info};
+use xsynth_core::soundfont::SampleSoundfont;
+use xsynth_core::AudioStreamParams;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SoundfontWorkerStatus {
+    Loading,
+    Finished,
+    Error,
+}
+
+#[derive(Clone)]
+struct SoundfontThread {
+    allow: Arc<AtomicBool>,
+    status: Arc<Atomic<SoundfontWorkerStatus>>,
+    error: Arc<RwLock<Option<String>>>,
+}
+
+impl SoundfontThread {
+    pub fn load_new(
+        soundfont: ForteSFListItem,
+        dest: Arc<RwLock<HashMap<SoundfontKey, Arc<SampleSoundfont>>>>,
+        audio_params: AudioStreamParams,
+    ) -> Self {
+        let status = Arc::new(Atomic::new(SoundfontWorkerStatus::Loading));
+        let statusc = status.clone();
+        let error = Arc::new(RwLock::new(None));
+        let errorc = error.clone();
+        let allow = Arc::new(AtomicBool::new(true));
+        let allowc = allow.clone();
+        thread::spawn(move || {
+            info!("Loading new soundfont: {:?}", soundfont.path);
+            let sf = SampleSoundfont::new(soundfont.path.clone(), audio_params, soundfont.init);
+            match sf {
+                Ok(sf) => {
+                    if allowc.load(Ordering::Relaxed) {
+                        info!("Finished loading soundfont: {:?}", soundfont.path);
+                        dest.write().unwrap().insert(soundfont.key(), Arc::new(sf));
+                    }
+                    statusc.store(SoundfontWorkerStatus::Finished, Ordering::Relaxed);
+                }
+                Err(err) => {
+                    error!("Error loading soundfont: {:?}: {:?}", soundfont.path, err);
+                    *errorc.write().unwrap() = Some(format!("{:?}: {}", soundfont.path, err));
+                    statusc.store(SoundfontWorkerStatus::Error, Ordering::Relaxed);
+                }
+            }
+        });
+
+        Self {
+            status,
+            allow,
+            error,
+        }
+    }
+
+    pub fn status(&self) -> SoundfontWorkerStatus {
+        self.status.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.allow.store(false, Ordering::Relaxed);
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.error.read().unwrap().clone()
+    }
+}
+
+pub struct SoundfontPool {
+    workers: Vec<SoundfontThread>,
+}
+
+impl SoundfontPool {
+    pub fn new(
+        soundfonts: Vec<ForteSFListItem>,
+        dest: Arc<RwLock<HashMap<SoundfontKey, Arc<SampleSoundfont>>>>,
+        audio_params: AudioStreamParams,
+    ) -> Self {
+        info!("Starting new soundfont thread manager");
+        let mut workers = Vec::new();
+
+        for soundfont in soundfonts.into_iter().filter(|s| s.enabled) {
+            workers.push(SoundfontThread::load_new(
+                soundfont,
+                dest.clone(),
+                audio_params,
+            ));
+        }
+
+        Self { workers }
+    }
+
+    pub fn cancel(&mut self) {
+        for w in &self.workers {
+            w.cancel();
+        }
+    }
+
+    /// Returns why the soundfont chain could not be loaded
+    pub fn error(&self) -> Option<String> {
+        self.workers.iter().find_map(SoundfontThread::error)
+    }
+
+    pub fn status(&mut self) -> SoundfontWorkerStatus {
+        let mut status = SoundfontWorkerStatus::Finished;
+
+        if self.workers.is_empty() {
+            return SoundfontWorkerStatus::Finished;
+        }
+
+        self.workers
+            .retain(|w| w.status() != SoundfontWorkerStatus::Finished);
+
+        for worker in &self.workers {
+            let s = worker.status();
+            status = s;
+            if s == SoundfontWorkerStatus::Error {
+                break;
+            }
+        }
+
+        if status == SoundfontWorkerStatus::Error {
+            self.cancel();
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SFFormat;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+    use xsynth_core::soundfont::{SoundfontBase, SoundfontInitOptions};
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    fn list(ty: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        chunk(b"LIST", &[&ty[..], &chunks.concat()].concat())
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(20, 0);
+        bytes
+    }
+
+    fn words(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    // An SF2 with one preset at bank 0, preset 0, which plays a short sine on every key
+    fn write_sf2(path: &Path) {
+        let samples: Vec<u8> = (0..1000)
+            .map(|i| ((i as f32 / 100.0 * std::f32::consts::TAU).sin() * 16000.0) as i16)
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+
+        let sample_header = |sample_name: &str, end: u32| {
+            let mut header = name(sample_name);
+            for value in [0, end, 0, end, 48000] {
+                header.extend_from_slice(&value.to_le_bytes());
+            }
+            // Root key, pitch correction, sample link and mono sample type
+            header.extend_from_slice(&[60, 0]);
+            header.extend(words(&[0, 1]));
+            header
+        };
+        let preset_header = |preset_name: &str, bag: u16| {
+            [name(preset_name), words(&[0, 0, bag]), vec![0; 12]].concat()
+        };
+
+        let info = list(
+            b"INFO",
+            &[chunk(b"ifil", &words(&[2, 1])), chunk(b"INAM", b"Test\0\0")],
+        );
+        let sdta = list(b"sdta", &[chunk(b"smpl", &samples)]);
+        let pdta = list(
+            b"pdta",
+            &[
+                chunk(
+                    b"phdr",
+                    &[preset_header("Sine", 0), preset_header("EOP", 1)].concat(),
+                ),
+                chunk(b"pbag", &words(&[0, 0, 1, 0])),
+                chunk(b"pmod", &[0; 10]),
+                // Instrument 0, then the terminal generator
+                chunk(b"pgen", &words(&[41, 0, 0, 0])),
+                chunk(
+                    b"inst",
+                    &[
+                        [name("Sine"), words(&[0])].concat(),
+                        [name("EOI"), words(&[1])].concat(),
+                    ]
+                    .concat(),
+                ),
+                chunk(b"ibag", &words(&[0, 0, 1, 0])),
+                chunk(b"imod", &[0; 10]),
+                // Sample 0, then the terminal generator
+                chunk(b"igen", &words(&[53, 0, 0, 0])),
+                chunk(
+                    b"shdr",
+                    &[sample_header("Sine", 1000), sample_header("EOS", 0)].concat(),
+                ),
+            ],
+        );
+
+        let body = [&b"sfbk"[..], &info, &sdta, &pdta].concat();
+        std::fs::write(path, chunk(b"RIFF", &body)).unwrap();
+    }
+
+    #[test]
+    fn loads_sf2() {
+        let dir = std::env::temp_dir().join(format!("forte-sf2-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sine.sf2");
+        write_sf2(&path);
+
+        let item = ForteSFListItem {
+            enabled: true,
+            format: SFFormat::Sf2,
+            path,
+            init: SoundfontInitOptions {
+                bank: None,
+                preset: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let soundfonts = Arc::new(RwLock::new(HashMap::new()));
+        let mut pool = SoundfontPool::new(
+            vec![item.clone()],
+            soundfonts.clone(),
+            AudioStreamParams::new(48000, 2.into()),
+        );
+
+        let start = Instant::now();
+        while pool.status() == SoundfontWorkerStatus::Loading {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "Loading timed out"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            pool.status(),
+            SoundfontWorkerStatus::Finished,
+            "{:?}",
+            pool.error()
+        );
+
+        let soundfonts = soundfonts.read().unwrap();
+        let sf = soundfonts.get(&item.key()).expect("The SF2 was not loaded");
+        assert!(!sf.get_attack_voice_spawners_at(0, 0, 60, 100).is_empty());
+        assert!(sf.get_attack_voice_spawners_at(0, 1, 60, 100).is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+/// The SysEx messages Forte understands
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SysExMessage {
+    GmReset,
+    GsReset,
+    XgReset,
+    /// GS "use for rhythm part", which turns a channel into a drum channel or back
+    RhythmPart {
+        channel: u8,
+        drums: bool,
+    },
+}
+
+/// The default drum channels, only channel 10 plays drums after a reset
+pub fn default_drum_channels() -> Vec<bool> {
+    (0..16).map(|i| i == 9).collect()
+}
+
+// GS parts are numbered with channel 10 first
+fn gs_part_to_channel(part: u8) -> Option<u8> {
+    match part {
+        0 => Some(9),
+        1..=9 => Some(part - 1),
+        10..=15 => Some(part),
+        _ => None,
+    }
+}
+
+/// Parses the data of a SysEx event. The leading 0xF0 and the trailing 0xF7 are
+/// optional, as not every MIDI library keeps them.
+pub fn parse_sysex(data: &[u8]) -> Option<SysExMessage> {
+    let data = data.strip_prefix(&[0xF0]).unwrap_or(data);
+    let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+
+    match data {
+        // Universal non-realtime, GM1 and GM2 system on
+        [0x7E, _, 0x09, 0x01 | 0x03] => Some(SysExMessage::GmReset),
+        // Roland GS, data set to the system mode or reset address
+        [0x41, _, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, _] => Some(SysExMessage::GsReset),
+        [0x41, _, 0x42, 0x12, 0x00, 0x00, 0x7F, 0x00 | 0x01, _] => Some(SysExMessage::GsReset),
+        [0x41, _, 0x42, 0x12, 0x40, block, 0x15, map, _] if block & 0xF0 == 0x10 => {
+            let channel = gs_part_to_channel(block & 0x0F)?;
+            Some(SysExMessage::RhythmPart {
+                channel,
+                drums: *map != 0,
+            })
+        }
+        // Yamaha XG system on
+        [0x43, device, 0x4C, 0x00, 0x00, 0x7E, 0x00] if device & 0xF0 == 0x10 => {
+            Some(SysExMessage::XgReset)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,160 @@
+use crate::utils::f64_to_time_str;
+use forte_core::job::{RenderEvent, RenderJob};
+use forte_core::settings::ForteConfig;
+use forte_core::xsynth::MIDIInput;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_RENDER_ERROR: i32 = 1;
+const EXIT_USAGE_ERROR: i32 = 2;
+
+const USAGE: &str = "Usage: forte --headless --config <config.toml> --output <dir> <midi>...
+
+Options:
+    -c, --config <file>    Config file in the Forte config.toml format
+    -o, --output <dir>     Directory the rendered audio files are written to
+    -h, --help             Print this message";
+
+struct HeadlessArgs {
+    config: PathBuf,
+    output: PathBuf,
+    midis: Vec<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Option<HeadlessArgs>, String> {
+    let mut config = None;
+    let mut output = None;
+    let mut midis = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-c" | "--config" => match iter.next() {
+                Some(path) => config = Some(PathBuf::from(path)),
+                None => return Err("Missing value for --config".to_owned()),
+            },
+            "-o" | "--output" => match iter.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err("Missing value for --output".to_owned()),
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+            _ => midis.push(PathBuf::from(arg)),
+        }
+    }
+
+    let config = config.ok_or("No config file specified")?;
+    let output = output.ok_or("No output directory specified")?;
+    if midis.is_empty() {
+        return Err("No MIDI files specified".to_owned());
+    }
+
+    Ok(Some(HeadlessArgs {
+        config,
+        output,
+        midis,
+    }))
+}
+
+fn midi_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+pub fn run_headless(args: &[String]) -> i32 {
+    info!("Launching Forte in headless mode");
+
+    let args = match parse_args(args) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return EXIT_SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    let mut config = match ForteConfig::load_from(&args.config) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Unable to load config file: {}", err);
+            eprintln!("Unable to load config file {:?}: {err}", args.config);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    if !args.output.is_dir() {
+        eprintln!("The output directory {:?} does not exist", args.output);
+        return EXIT_USAGE_ERROR;
+    }
+    config.render_settings.output_dir = Some(args.output);
+
+    let names: Vec<String> = args.midis.iter().map(|path| midi_name(path)).collect();
+    let count = names.len();
+
+    println!("Loading soundfonts");
+    let midis = args.midis.into_iter().map(MIDIInput::from).collect();
+    let mut job = match RenderJob::new(config, midis) {
+        Ok(job) => job,
+        Err(err) => {
+            error!("Unable to create render job: {}", err);
+            eprintln!("{err}");
+            return EXIT_RENDER_ERROR;
+        }
+    };
+
+    let mut failed = 0;
+    let mut last_progress = vec![Instant::now(); count];
+    for event in job.start() {
+        match event {
+            RenderEvent::SoundfontsLoaded => println!("Rendering {count} MIDI(s)"),
+            RenderEvent::SoundfontError { error } => {
+                eprintln!("Soundfont Loader Error: {error}");
+                return EXIT_RENDER_ERROR;
+            }
+            RenderEvent::Progress {
+                id,
+                time,
+                voice_count,
+            } => {
+                if last_progress[id].elapsed() >= PROGRESS_INTERVAL {
+                    println!(
+                        "[{}/{}] {}: {} rendered, {} voices",
+                        id + 1,
+                        count,
+                        names[id],
+                        f64_to_time_str(time),
+                        voice_count
+                    );
+                    last_progress[id] = Instant::now();
+                }
+            }
+            RenderEvent::MIDIFinished { id } => {
+                println!("[{}/{}] {}: finished", id + 1, count, names[id]);
+            }
+            RenderEvent::MIDIFailed { id, error } => {
+                eprintln!("[{}/{}] {}: failed: {}", id + 1, count, names[id], error);
+                failed += 1;
+            }
+            RenderEvent::Cancelled => return EXIT_RENDER_ERROR,
+            RenderEvent::Finished => break,
+        }
+    }
+
+    info!("Conversion finished with {} error(s)", failed);
+    println!("Rendered {} of {} MIDI(s)", count - failed, count);
+
+    if failed > 0 {
+        EXIT_RENDER_ERROR
+    } else {
+        EXIT_SUCCESS
+    }
+}
@@ -1,4 +1,5 @@
 pub mod channel_cfg;
+pub mod effect_chain;
 pub mod midi_list;
 pub mod persistent_message;
 pub mod render_settings;
@@ -1,5 +1,7 @@
-use crate::settings::SingleChannelSettings;
+use crate::elements::effect_chain::show_effect_chain;
 use egui::Ui;
+use forte_core::settings::{ChannelMixerSettings, SingleChannelSettings, VelocityCurve};
+use std::ops::RangeInclusive;
 use xsynth_core::channel::ChannelInitOptions;
 
 pub struct EguiChannelConfig {
@@ -7,6 +9,35 @@ pub struct EguiChannelConfig {
     layer_count: usize,
     init: ChannelInitOptions,
     use_threadpool: bool,
+    mixer: ChannelMixerSettings,
+    aftertouch_cc: Option<u8>,
+    map_poly_aftertouch: bool,
+    pitch_bend_range: f32,
+    velocity_curve: VelocityCurve,
+    key_range: RangeInclusive<u8>,
+    // The lookup table as it is typed, only applied once it holds 128 velocities
+    velocity_table_text: String,
+}
+
+fn table_to_text(table: &[u8]) -> String {
+    table
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn text_to_table(text: &str) -> Option<Vec<u8>> {
+    let table = text
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<u8>().ok().filter(|v| *v < 128))
+        .collect::<Option<Vec<u8>>>()?;
+    if table.len() == 128 {
+        Some(table)
+    } else {
+        None
+    }
 }
 
 impl EguiChannelConfig {
@@ -16,6 +47,16 @@ impl EguiChannelConfig {
             layer_count: settings.layer_limit,
             init: settings.channel_init_options,
             use_threadpool: settings.use_threadpool,
+            mixer: settings.mixer.clone(),
+            aftertouch_cc: settings.aftertouch_cc,
+            map_poly_aftertouch: settings.map_poly_aftertouch,
+            pitch_bend_range: settings.pitch_bend_range,
+            velocity_curve: settings.velocity_curve.clone(),
+            key_range: settings.key_range.clone(),
+            velocity_table_text: match &settings.velocity_curve {
+                VelocityCurve::Table { table } => table_to_text(table),
+                _ => table_to_text(&VelocityCurve::Unchanged.to_table()),
+            },
         }
     }
 
@@ -24,6 +65,12 @@ impl EguiChannelConfig {
         settings.layer_limit = self.layer_count;
         settings.layer_limit_enabled = self.limit_layers;
         settings.use_threadpool = self.use_threadpool;
+        settings.mixer = self.mixer.clone();
+        settings.aftertouch_cc = self.aftertouch_cc;
+        settings.map_poly_aftertouch = self.map_poly_aftertouch;
+        settings.pitch_bend_range = self.pitch_bend_range;
+        settings.velocity_curve = self.velocity_curve.clone();
+        settings.key_range = self.key_range.clone();
     }
 
     pub fn show(&mut self, ui: &mut Ui) {
@@ -51,6 +98,41 @@ impl EguiChannelConfig {
                     );
                 });
                 ui.end_row();
+
+                ui.label("Pitch Bend Range: ");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut self.pitch_bend_range)
+                            .speed(0.1)
+                            .clamp_range(0.0..=24.0)
+                            .suffix(" semitones"),
+                    );
+                    ui.label("\u{2139}").on_hover_text(
+                        "The default range of the pitch wheel.\nMIDIs can change it with RPN 0.",
+                    );
+                });
+                ui.end_row();
+
+                ui.label("Key Range: ");
+                let mut lokey = *self.key_range.start();
+                let mut hikey = *self.key_range.end();
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut lokey)
+                            .speed(1)
+                            .clamp_range(0..=hikey),
+                    );
+                    ui.label("to");
+                    ui.add(
+                        egui::DragValue::new(&mut hikey)
+                            .speed(1)
+                            .clamp_range(lokey..=127),
+                    );
+                    ui.label("\u{2139}")
+                        .on_hover_text("Notes outside of this range are not played.");
+                });
+                ui.end_row();
+                self.key_range = lokey..=hikey;
             });
         ui.add_space(5.0);
 
@@ -87,5 +169,165 @@ impl EguiChannelConfig {
                 ui.end_row();
             });
         ui.add_space(5.0);
+
+        ui.heading("Aftertouch");
+        ui.separator();
+        egui::Grid::new("aftertouch_synth_settings_grid")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .min_col_width(140.0)
+            .show(ui, |ui| {
+                let mut map_aftertouch = self.aftertouch_cc.is_some();
+                let mut cc = self.aftertouch_cc.unwrap_or(11);
+
+                ui.label("Map Aftertouch to CC: ");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut map_aftertouch, "");
+                    ui.add_enabled(
+                        map_aftertouch,
+                        egui::DragValue::new(&mut cc).speed(1).clamp_range(0..=119),
+                    );
+                    ui.label("\u{2139}").on_hover_text(
+                        "Sends channel pressure as a control change.\nCC11 (expression) or CC1 (modulation) work with most soundfonts.",
+                    );
+                });
+                ui.end_row();
+
+                self.aftertouch_cc = if map_aftertouch { Some(cc) } else { None };
+
+                ui.label("Include Poly Aftertouch: ");
+                ui.horizontal(|ui| {
+                    ui.add_enabled(
+                        map_aftertouch,
+                        egui::Checkbox::new(&mut self.map_poly_aftertouch, ""),
+                    );
+                    ui.label("\u{2139}").on_hover_text(
+                        "Limitation: XSynth can not change single notes, so the pressure of a key\nis applied to every note on the channel and the key is ignored.\nThe last pressure sent for any key sets the CC.",
+                    );
+                });
+                ui.end_row();
+            });
+        ui.add_space(5.0);
+
+        ui.heading("Velocity");
+        ui.separator();
+        egui::Grid::new("velocity_synth_settings_grid")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .min_col_width(140.0)
+            .show(ui, |ui| {
+                ui.label("Velocity Curve: ");
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("velocity_curve_select")
+                        .selected_text(format!("{}", self.velocity_curve))
+                        .show_ui(ui, |ui| {
+                            for curve in VelocityCurve::all() {
+                                let selected = std::mem::discriminant(&curve)
+                                    == std::mem::discriminant(&self.velocity_curve);
+                                if ui
+                                    .selectable_label(selected, format!("{curve}"))
+                                    .clicked()
+                                    && !selected
+                                {
+                                    self.velocity_curve = curve;
+                                }
+                            }
+                        });
+                    ui.label("\u{2139}").on_hover_text(
+                        "Changes the velocity of the notes.\nThe velocity ignore range still uses the original velocity.",
+                    );
+                });
+                ui.end_row();
+
+                match &mut self.velocity_curve {
+                    VelocityCurve::Unchanged => {}
+                    VelocityCurve::Linear { scale, offset } => {
+                        ui.label("Scale: ");
+                        ui.add(
+                            egui::DragValue::new(scale)
+                                .speed(0.01)
+                                .clamp_range(0.0..=4.0),
+                        );
+                        ui.end_row();
+
+                        ui.label("Offset: ");
+                        ui.add(
+                            egui::DragValue::new(offset)
+                                .speed(1.0)
+                                .clamp_range(-127.0..=127.0),
+                        );
+                        ui.end_row();
+                    }
+                    VelocityCurve::Exponential { exponent } => {
+                        ui.label("Exponent: ");
+                        ui.add(
+                            egui::DragValue::new(exponent)
+                                .speed(0.01)
+                                .clamp_range(0.1..=4.0),
+                        );
+                        ui.end_row();
+                    }
+                    VelocityCurve::Table { table } => {
+                        ui.label("Lookup Table: ");
+                        ui.vertical(|ui| {
+                            if ui
+                                .text_edit_multiline(&mut self.velocity_table_text)
+                                .changed()
+                            {
+                                if let Some(new) = text_to_table(&self.velocity_table_text) {
+                                    *table = new;
+                                }
+                            }
+                            if text_to_table(&self.velocity_table_text).is_none() {
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(255, 100, 100),
+                                    "The table needs 128 velocities from 0 to 127",
+                                );
+                            }
+                        });
+                        ui.end_row();
+                    }
+                }
+            });
+        ui.add_space(5.0);
+
+        ui.heading("Mixer");
+        ui.separator();
+        egui::Grid::new("mixer_synth_settings_grid")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .min_col_width(140.0)
+            .show(ui, |ui| {
+                ui.label("Gain (dB): ");
+                ui.add(
+                    egui::DragValue::new(&mut self.mixer.gain_db)
+                        .speed(0.1)
+                        .clamp_range(-60.0..=24.0),
+                );
+                ui.end_row();
+
+                ui.label("Pan: ");
+                ui.add(egui::Slider::new(&mut self.mixer.pan, -1.0..=1.0));
+                ui.end_row();
+
+                ui.label("Mute: ");
+                ui.checkbox(&mut self.mixer.mute, "");
+                ui.end_row();
+
+                ui.label("Solo: ");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.mixer.solo, "");
+                    ui.label("\u{2139}").on_hover_text(
+                        "If any channel is soloed, only the soloed channels are rendered.",
+                    );
+                });
+                ui.end_row();
+            });
+        ui.add_space(5.0);
+
+        ui.heading("Insert Effects");
+        ui.separator();
+        show_effect_chain(ui, "channel_insert", &mut self.mixer.effects);
+        ui.add_space(5.0);
     }
 }
@@ -0,0 +1,102 @@
+use crate::utils::render_in_frame;
+use egui::Ui;
+use forte_core::dsp::DSPEffect;
+
+fn param(ui: &mut Ui, name: &str, value: &mut f32, speed: f32, range: (f32, f32)) {
+    ui.label(name);
+    ui.add(
+        egui::DragValue::new(value)
+            .speed(speed)
+            .clamp_range(range.0..=range.1),
+    );
+    ui.end_row();
+}
+
+fn show_effect_params(ui: &mut Ui, effect: &mut DSPEffect) {
+    match effect {
+        DSPEffect::Equalizer {
+            frequency,
+            q,
+            gain_db,
+        } => {
+            param(ui, "Frequency (Hz): ", frequency, 5.0, (20.0, 20000.0));
+            param(ui, "Q: ", q, 0.01, (0.1, 20.0));
+            param(ui, "Gain (dB): ", gain_db, 0.1, (-24.0, 24.0));
+        }
+        DSPEffect::Compressor {
+            threshold_db,
+            ratio,
+            attack_ms,
+            release_ms,
+            makeup_db,
+        } => {
+            param(ui, "Threshold (dB): ", threshold_db, 0.1, (-60.0, 0.0));
+            param(ui, "Ratio: ", ratio, 0.1, (1.0, 20.0));
+            param(ui, "Attack (ms): ", attack_ms, 0.1, (0.1, 200.0));
+            param(ui, "Release (ms): ", release_ms, 1.0, (10.0, 2000.0));
+            param(ui, "Makeup Gain (dB): ", makeup_db, 0.1, (0.0, 24.0));
+        }
+        DSPEffect::Reverb {
+            room_size,
+            time,
+            wet,
+        } => {
+            param(ui, "Room Size (m): ", room_size, 0.1, (1.0, 100.0));
+            param(ui, "Decay Time (s): ", time, 0.05, (0.1, 20.0));
+            param(ui, "Wet Mix: ", wet, 0.01, (0.0, 1.0));
+        }
+        DSPEffect::HighPass { frequency, q } | DSPEffect::LowPass { frequency, q } => {
+            param(ui, "Cutoff (Hz): ", frequency, 5.0, (20.0, 20000.0));
+            param(ui, "Q: ", q, 0.01, (0.1, 20.0));
+        }
+        DSPEffect::DCBlocker { frequency } => {
+            param(ui, "Cutoff (Hz): ", frequency, 0.1, (1.0, 50.0));
+        }
+        DSPEffect::StereoWidth { width } => {
+            param(ui, "Width: ", width, 0.01, (0.0, 2.0));
+        }
+    }
+}
+
+/// Shows an editable list of effects. `id` has to be unique for every list.
+pub fn show_effect_chain(ui: &mut Ui, id: &str, effects: &mut Vec<DSPEffect>) {
+    let mut move_up = None;
+    let mut remove = None;
+
+    for (i, effect) in effects.iter_mut().enumerate() {
+        render_in_frame(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.strong(format!("{}. {}", i + 1, effect));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                    if ui.add_enabled(i > 0, egui::Button::new("Up")).clicked() {
+                        move_up = Some(i);
+                    }
+                });
+            });
+            egui::Grid::new(format!("{id}_effect_{i}"))
+                .num_columns(2)
+                .spacing([5.0, 4.0])
+                .show(ui, |ui| show_effect_params(ui, effect));
+        });
+    }
+
+    if let Some(i) = move_up {
+        effects.swap(i - 1, i);
+    }
+    if let Some(i) = remove {
+        effects.remove(i);
+    }
+
+    egui::ComboBox::from_id_source(format!("{id}_add_effect"))
+        .selected_text("Add Effect")
+        .show_ui(ui, |ui| {
+            for effect in DSPEffect::all() {
+                if ui.selectable_label(false, format!("{effect}")).clicked() {
+                    effects.push(effect);
+                }
+            }
+        });
+}
@@ -1,11 +1,12 @@
 use crate::app::add_gui_error;
-use crate::errors::error_types::FileLoadError;
 use crate::utils::{bytes_to_filesize_str, f64_to_time_str};
-use crate::xsynth::RenderStats;
 use egui::{containers::scroll_area::ScrollArea, Context, Ui, Window};
 use egui_extras::{Column, TableBuilder};
+use forte_core::errors::error_types::FileLoadError;
+use forte_core::midi::{is_midi_file, MIDISource};
+use forte_core::xsynth::RenderStats;
 use midi_toolkit::{
-    io::{MIDIFile, MIDILoadError},
+    io::MIDILoadError,
     pipe,
     sequence::{event::get_channels_array_statistics, to_vec},
 };
@@ -17,6 +18,8 @@ use tracing::{info, warn};
 pub struct ForteListItem {
     pub selected: bool,
     pub path: PathBuf,
+    // Kept so compressed MIDIs are only decompressed once
+    pub source: MIDISource,
     pub filesize: u64,
     pub length: f64,
     pub note_count: u64,
@@ -44,54 +47,54 @@ impl EguiMIDIList {
             return Err(FileLoadError::FileNotFound);
         }
 
-        if let Some(ext) = path.extension() {
-            if ext == "mid" {
-                info!("Streaming MIDI from disk");
-                let file = MIDIFile::open(path.clone(), None);
-                match file {
-                    Ok(midi) => {
-                        info!("Gathering MIDI stats");
-                        let stats = pipe!(
-                            midi.iter_all_tracks()|>to_vec()|>get_channels_array_statistics().unwrap()
-                        );
-
-                        let length = stats.calculate_total_duration(midi.ppq()).as_secs_f64();
-                        let filesize = std::fs::metadata(path.clone()).unwrap().len();
-
-                        let item = ForteListItem {
-                            selected: false,
-                            path,
-                            filesize,
-                            length,
-                            note_count: stats.note_count(),
-                            context_menu_visible: false,
-                            stats_visible: false,
-                        };
-                        self.list.push(item);
-                        Ok(())
-                    }
-                    Err(error) => match error {
-                        MIDILoadError::CorruptChunks => {
-                            warn!("The selected MIDI has corrupt chunks");
-                            Err(FileLoadError::Corrupt("Corrupt chunks".to_owned()))
-                        }
-                        MIDILoadError::FilesystemError(fserr) => {
-                            warn!("Filesystem error: {fserr}");
-                            Err(FileLoadError::Corrupt(format!("Filesystem error: {fserr}")))
-                        }
-                        MIDILoadError::FileTooBig => {
-                            warn!("The selected MIDI file is too big");
-                            Err(FileLoadError::Corrupt("MIDI file too big".to_owned()))
-                        }
-                    },
-                }
-            } else {
-                warn!("The selected MIDI file does not have the correct format");
-                Err(FileLoadError::InvalidFormat)
+        if !is_midi_file(&path) {
+            warn!("The selected file is not a MIDI");
+            return Err(FileLoadError::InvalidFormat);
+        }
+
+        self.add_midi(path)
+    }
+
+    fn add_midi(&mut self, path: PathBuf) -> Result<(), FileLoadError> {
+        info!("Streaming MIDI from disk");
+        let file = MIDISource::new(&path).and_then(|source| Ok((source.open()?, source)));
+        match file {
+            Ok((midi, source)) => {
+                info!("Gathering MIDI stats");
+                let stats = pipe!(
+                    midi.iter_all_tracks()|>to_vec()|>get_channels_array_statistics().unwrap()
+                );
+
+                let length = stats.calculate_total_duration(midi.ppq()).as_secs_f64();
+
+                let item = ForteListItem {
+                    selected: false,
+                    path,
+                    // Compressed MIDIs report the size of their content
+                    filesize: source.size(),
+                    source,
+                    length,
+                    note_count: stats.note_count(),
+                    context_menu_visible: false,
+                    stats_visible: false,
+                };
+                self.list.push(item);
+                Ok(())
             }
-        } else {
-            warn!("The selected MIDI file does not have the correct format");
-            Err(FileLoadError::InvalidFormat)
+            Err(error) => match error {
+                MIDILoadError::CorruptChunks => {
+                    warn!("The selected MIDI has corrupt chunks");
+                    Err(FileLoadError::Corrupt("Corrupt chunks".to_owned()))
+                }
+                MIDILoadError::FilesystemError(fserr) => {
+                    warn!("Filesystem error: {fserr}");
+                    Err(FileLoadError::Corrupt(format!("Filesystem error: {fserr}")))
+                }
+                MIDILoadError::FileTooBig => {
+                    warn!("The selected MIDI file is too big");
+                    Err(FileLoadError::Corrupt("MIDI file too big".to_owned()))
+                }
+            },
         }
     }
 
@@ -103,10 +106,8 @@ impl EguiMIDIList {
                 let p = p.path();
                 if p.is_dir() {
                     result = self.add_folder(p);
-                } else if let Some(ext) = p.extension() {
-                    if ext == "mid" {
-                        result = self.add_item(p);
-                    }
+                } else if is_midi_file(&p) {
+                    result = self.add_midi(p);
                 }
             }
         } else {
@@ -1,6 +1,13 @@
-use crate::settings::{ForteState, OutputAudioFormat, PCMSampleFormat, RenderMode};
-use crate::writer::{COMMON_BITRATES, COMMON_SAMPLE_RATES};
+use crate::elements::effect_chain::show_effect_chain;
+use crate::settings::ForteState;
+use crate::utils::{str_to_track_groups, track_groups_to_str};
 use egui::Ui;
+use forte_core::dsp::LimiterMode;
+use forte_core::settings::{
+    DitherMode, NoteDropMode, OutputAudioFormat, PCMSampleFormat, RenderMode, RenderRangeUnit,
+    StemMode, TransposeMode, WavContainer,
+};
+use forte_core::writer::{COMMON_BITRATES, COMMON_SAMPLE_RATES};
 
 pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
     let large_label = "Ignore Notes with Velocities Between: ";
@@ -76,6 +83,36 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
                 state.render_settings.vel_ignore_range = lovel..=hivel;
             }
 
+            let filter = &mut state.render_settings.note_filter;
+            ui.label("Ignore Notes Shorter Than: ");
+            ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                ui.horizontal(|ui| {
+                    let mut enabled = filter.min_length.is_some();
+                    let mut ticks = filter.min_length.unwrap_or(1);
+                    ui.checkbox(&mut enabled, "");
+                    ui.add_enabled(
+                        enabled,
+                        egui::DragValue::new(&mut ticks)
+                            .speed(1)
+                            .clamp_range(1..=u16::MAX as u32)
+                            .suffix(" ticks"),
+                    );
+                    filter.min_length = if enabled { Some(ticks) } else { None };
+                });
+            });
+            ui.end_row();
+
+            ui.label("Remove Overlapping Notes: ");
+            ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut filter.remove_overlaps, "");
+                    ui.label("\u{2139}").on_hover_text(
+                        "Only keeps one note when a key is played again on the same channel while it is held.",
+                    );
+                });
+            });
+            ui.end_row();
+
             ui.label("Realtime Simulation FPS: ");
             let buffer = state.render_settings.realtime_buffer_ms;
             let mut fps = 1000.0 / buffer;
@@ -90,6 +127,146 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
             if fps != fps_prev {
                 state.render_settings.realtime_buffer_ms = 1000.0 / fps;
             }
+
+            let realtime = matches!(
+                state.render_settings.render_mode,
+                RenderMode::RealtimeSimulation
+            );
+            let note_drop = &mut state.render_settings.note_drop;
+
+            ui.label("Skip Notes When Overloaded: ");
+            ui.add_enabled_ui(!state.ui_state.rendering && realtime, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("note_drop_mode_selector")
+                        .selected_text(format!("{}", note_drop.mode))
+                        .show_ui(ui, |ui| {
+                            for mode in [
+                                NoteDropMode::Disabled,
+                                NoteDropMode::EventsPerBuffer,
+                                NoteDropMode::MaxNps,
+                            ] {
+                                ui.selectable_value(&mut note_drop.mode, mode, format!("{mode}"));
+                            }
+                        });
+                    ui.label("\u{2139}").on_hover_text(
+                        "Skips notes like a live player that can not keep up,\nso the render sounds like it would on stream.",
+                    );
+                });
+            });
+            ui.end_row();
+
+            ui.label("Events per Buffer: ");
+            ui.add_enabled(
+                !state.ui_state.rendering
+                    && realtime
+                    && note_drop.mode == NoteDropMode::EventsPerBuffer,
+                egui::DragValue::new(&mut note_drop.events_per_buffer)
+                    .speed(10)
+                    .clamp_range(1..=u32::MAX),
+            );
+            ui.end_row();
+
+            ui.label("Max Notes per Second: ");
+            ui.add_enabled(
+                !state.ui_state.rendering && realtime && note_drop.mode == NoteDropMode::MaxNps,
+                egui::DragValue::new(&mut note_drop.max_nps)
+                    .speed(100)
+                    .clamp_range(1..=u32::MAX),
+            );
+            ui.end_row();
+
+            ui.label("Playback Speed: ");
+            ui.add_enabled(
+                !state.ui_state.rendering,
+                egui::DragValue::new(&mut state.render_settings.speed)
+                    .speed(0.01)
+                    .clamp_range(0.5..=4.0)
+                    .suffix("x"),
+            );
+            ui.end_row();
+
+            ui.label("Transpose (semitones): ");
+            ui.add_enabled(
+                !state.ui_state.rendering,
+                egui::DragValue::new(&mut state.render_settings.transpose)
+                    .speed(0.1)
+                    .clamp_range(-48..=48),
+            );
+            ui.end_row();
+
+            ui.label("Transposed Out of Range: ");
+            ui.add_enabled_ui(
+                !state.ui_state.rendering && state.render_settings.transpose != 0,
+                |ui| {
+                    egui::ComboBox::from_id_source("transpose_mode_selector")
+                        .selected_text(format!("{}", state.render_settings.transpose_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in [TransposeMode::Drop, TransposeMode::Clamp] {
+                                ui.selectable_value(
+                                    &mut state.render_settings.transpose_mode,
+                                    mode,
+                                    format!("{mode}"),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("What to do with notes pushed outside of the key range");
+                },
+            );
+            ui.end_row();
+
+            let range = &mut state.render_settings.render_range;
+
+            ui.label("Render Time Range: ");
+            ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                ui.checkbox(&mut range.enabled, "")
+                    .on_hover_text("Only render a part of the MIDI");
+            });
+            ui.end_row();
+
+            ui.label("Range Unit: ");
+            ui.add_enabled_ui(!state.ui_state.rendering && range.enabled, |ui| {
+                egui::ComboBox::from_id_source("render_range_unit_selector")
+                    .selected_text(format!("{}", range.unit))
+                    .show_ui(ui, |ui| {
+                        for unit in [RenderRangeUnit::Seconds, RenderRangeUnit::Ticks] {
+                            ui.selectable_value(&mut range.unit, unit, format!("{unit}"));
+                        }
+                    });
+            });
+            ui.end_row();
+
+            let speed = match range.unit {
+                RenderRangeUnit::Seconds => 0.1,
+                RenderRangeUnit::Ticks => 10.0,
+            };
+
+            ui.label("Range Start: ");
+            ui.add_enabled(
+                !state.ui_state.rendering && range.enabled,
+                egui::DragValue::new(&mut range.start)
+                    .speed(speed)
+                    .clamp_range(0.0..=f64::MAX),
+            );
+            ui.end_row();
+
+            ui.label("Range End: ");
+            ui.add_enabled_ui(!state.ui_state.rendering && range.enabled, |ui| {
+                ui.horizontal(|ui| {
+                    let mut has_end = range.end.is_some();
+                    ui.checkbox(&mut has_end, "")
+                        .on_hover_text("Render until the end of the MIDI if disabled");
+                    let mut end = range.end.unwrap_or(range.start);
+                    ui.add_enabled(
+                        has_end,
+                        egui::DragValue::new(&mut end)
+                            .speed(speed)
+                            .clamp_range(range.start..=f64::MAX),
+                    );
+                    range.end = if has_end { Some(end) } else { None };
+                });
+            });
+            ui.end_row();
         });
 
     ui.add_space(5.0);
@@ -145,24 +322,56 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
                 Wav,
                 Ogg,
                 Mp3,
+                Flac,
+                Opus,
             }
 
-            let (mut tmpformat, mut pcmformat, mut bitrate) =
+            let (mut tmpformat, mut pcmformat, mut bitrate, mut compression_level) =
                 match &state.render_settings.audio_format {
-                    OutputAudioFormat::Pcm { format } => {
-                        (TemporaryAudioFormat::Wav, *format, 192000u32)
+                    OutputAudioFormat::Pcm { format, .. } => {
+                        (TemporaryAudioFormat::Wav, *format, 192000u32, 5u8)
                     }
                     OutputAudioFormat::Vorbis { bitrate } => (
                         TemporaryAudioFormat::Ogg,
                         PCMSampleFormat::Float32,
                         *bitrate,
+                        5,
                     ),
                     OutputAudioFormat::Lame { bitrate } => (
                         TemporaryAudioFormat::Mp3,
                         PCMSampleFormat::Float32,
                         *bitrate,
+                        5,
+                    ),
+                    OutputAudioFormat::Flac {
+                        compression_level, ..
+                    } => (
+                        TemporaryAudioFormat::Flac,
+                        PCMSampleFormat::Float32,
+                        192000,
+                        *compression_level,
+                    ),
+                    OutputAudioFormat::Opus { bitrate, .. } => (
+                        TemporaryAudioFormat::Opus,
+                        PCMSampleFormat::Float32,
+                        *bitrate,
+                        5,
                     ),
                 };
+            let mut complexity = match &state.render_settings.audio_format {
+                OutputAudioFormat::Opus { complexity, .. } => *complexity,
+                _ => 10,
+            };
+            let mut dither = match &state.render_settings.audio_format {
+                OutputAudioFormat::Pcm { dither, .. } | OutputAudioFormat::Flac { dither, .. } => {
+                    *dither
+                }
+                _ => DitherMode::Tpdf,
+            };
+            let mut container = match &state.render_settings.audio_format {
+                OutputAudioFormat::Pcm { container, .. } => *container,
+                _ => WavContainer::Auto,
+            };
 
             ui.label("Audio Format: ");
             ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
@@ -172,6 +381,8 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
                         ui.selectable_value(&mut tmpformat, TemporaryAudioFormat::Wav, "WAV");
                         ui.selectable_value(&mut tmpformat, TemporaryAudioFormat::Ogg, "OGG");
                         ui.selectable_value(&mut tmpformat, TemporaryAudioFormat::Mp3, "MP3");
+                        ui.selectable_value(&mut tmpformat, TemporaryAudioFormat::Flac, "FLAC");
+                        ui.selectable_value(&mut tmpformat, TemporaryAudioFormat::Opus, "OPUS");
                     })
             });
             ui.end_row();
@@ -183,24 +394,71 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
                         egui::ComboBox::from_id_source("render_pcm_format_selector")
                             .selected_text(format!("{}", pcmformat))
                             .show_ui(ui, |ui| {
-                                ui.selectable_value(
-                                    &mut pcmformat,
+                                for f in [
                                     PCMSampleFormat::Int16,
-                                    "16-bit integer",
-                                );
-                                ui.selectable_value(
-                                    &mut pcmformat,
+                                    PCMSampleFormat::Int24,
                                     PCMSampleFormat::Float32,
-                                    "32-bit float",
-                                );
+                                    PCMSampleFormat::Float64,
+                                ] {
+                                    ui.selectable_value(&mut pcmformat, f, format!("{f}"));
+                                }
                             })
                     });
                     ui.end_row();
 
-                    state.render_settings.audio_format =
-                        OutputAudioFormat::Pcm { format: pcmformat };
+                    ui.label("Dither: ");
+                    ui.add_enabled_ui(!state.ui_state.rendering && pcmformat.is_integer(), |ui| {
+                        dither_selector(ui, &mut dither)
+                    });
+                    ui.end_row();
+
+                    ui.label("WAV Container: ");
+                    ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                        egui::ComboBox::from_id_source("render_wav_container_selector")
+                            .selected_text(format!("{}", container))
+                            .show_ui(ui, |ui| {
+                                for c in [WavContainer::Auto, WavContainer::Wave64] {
+                                    ui.selectable_value(&mut container, c, format!("{c}"));
+                                }
+                            })
+                    })
+                    .response
+                    .on_hover_text("WAV / RF64 switches to RF64 for files over 4GB");
+                    ui.end_row();
+
+                    state.render_settings.audio_format = OutputAudioFormat::Pcm {
+                        format: pcmformat,
+                        dither,
+                        container,
+                    };
                 }
-                TemporaryAudioFormat::Ogg | TemporaryAudioFormat::Mp3 => {
+                TemporaryAudioFormat::Flac => {
+                    ui.label("Compression Level: ");
+                    ui.add_enabled(
+                        !state.ui_state.rendering,
+                        egui::DragValue::new(&mut compression_level)
+                            .speed(0.1)
+                            .clamp_range(0..=8),
+                    )
+                    .on_hover_text("Higher levels make smaller files but encode slower");
+                    ui.end_row();
+
+                    ui.label("Dither: ");
+                    ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                        dither_selector(ui, &mut dither)
+                    })
+                    .response
+                    .on_hover_text("FLAC files are written with 24 bit samples");
+                    ui.end_row();
+
+                    state.render_settings.audio_format = OutputAudioFormat::Flac {
+                        compression_level,
+                        dither,
+                    };
+                }
+                TemporaryAudioFormat::Ogg
+                | TemporaryAudioFormat::Mp3
+                | TemporaryAudioFormat::Opus => {
                     ui.label("Bitrate: ");
                     ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
                         egui::ComboBox::from_id_source("render_bitrate_selector")
@@ -225,10 +483,94 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
                         TemporaryAudioFormat::Mp3 => {
                             state.render_settings.audio_format = OutputAudioFormat::Lame { bitrate }
                         }
+                        TemporaryAudioFormat::Opus => {
+                            ui.label("Complexity: ");
+                            ui.add_enabled(
+                                !state.ui_state.rendering,
+                                egui::DragValue::new(&mut complexity)
+                                    .speed(0.1)
+                                    .clamp_range(0..=10),
+                            )
+                            .on_hover_text("Higher values sound better but encode slower");
+                            ui.end_row();
+
+                            state.render_settings.audio_format = OutputAudioFormat::Opus {
+                                bitrate,
+                                complexity,
+                            }
+                        }
                         _ => {}
                     }
                 }
             }
+
+            ui.label("Stem Export: ");
+            ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("render_stem_mode_selector")
+                        .selected_text(format!("{}", state.render_settings.stem_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in
+                                [StemMode::Disabled, StemMode::PerChannel, StemMode::PerTrack]
+                            {
+                                ui.selectable_value(
+                                    &mut state.render_settings.stem_mode,
+                                    mode,
+                                    format!("{mode}"),
+                                );
+                            }
+                        });
+                    ui.label("\u{2139}").on_hover_text(
+                        "Stems skip the DSP effects and the limiter.\nThey get the normalization gain of the full mix, so their balance matches it.",
+                    );
+                });
+            });
+            ui.end_row();
+
+            ui.label("Also Write Full Mix: ");
+            ui.add_enabled_ui(
+                !state.ui_state.rendering && state.render_settings.stem_mode != StemMode::Disabled,
+                |ui| {
+                    ui.checkbox(&mut state.render_settings.stems_include_mix, "");
+                },
+            );
+            ui.end_row();
+
+            ui.label("Track Groups: ");
+            ui.add_enabled_ui(
+                !state.ui_state.rendering && state.render_settings.stem_mode == StemMode::PerTrack,
+                |ui| {
+                    ui.vertical(|ui| {
+                        let response = ui
+                            .add(
+                                egui::TextEdit::singleline(
+                                    &mut state.ui_state.stem_track_groups_text,
+                                )
+                                .hint_text("One stem per track"),
+                            )
+                            .on_hover_text(
+                                "Tracks rendered to the same stem, e.g. \"1-4; 5, 7\". \
+                                Tracks outside of every group share an extra stem.\n\
+                                Without groups, MIDIs with more than 64 tracks can't be \
+                                rendered to stems.",
+                            );
+                        let groups = str_to_track_groups(&state.ui_state.stem_track_groups_text);
+                        if response.changed() {
+                            if let Ok(groups) = &groups {
+                                state.render_settings.stem_track_groups = groups.clone();
+                            }
+                        } else if !response.has_focus() {
+                            state.ui_state.stem_track_groups_text =
+                                track_groups_to_str(&state.render_settings.stem_track_groups);
+                        }
+                        // The last valid groups are kept until the text is fixed
+                        if let Err(err) = groups {
+                            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), err);
+                        }
+                    });
+                },
+            );
+            ui.end_row();
         });
 
     ui.add_space(5.0);
@@ -245,6 +587,46 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
             });
             ui.end_row();
 
+            let limiter_enabled = state.render_settings.dsp_settings.limiter.enabled;
+            let linked = state.render_settings.dsp_settings.limiter.mode == LimiterMode::Linked;
+
+            ui.label("Limiter Mode: ");
+            ui.add_enabled_ui(!state.ui_state.rendering && limiter_enabled, |ui| {
+                egui::ComboBox::from_id_source("dsp_limiter_mode_selector")
+                    .selected_text(format!(
+                        "{}",
+                        state.render_settings.dsp_settings.limiter.mode
+                    ))
+                    .show_ui(ui, |ui| {
+                        for mode in [LimiterMode::Unlinked, LimiterMode::Linked] {
+                            ui.selectable_value(
+                                &mut state.render_settings.dsp_settings.limiter.mode,
+                                mode,
+                                format!("{mode}"),
+                            );
+                        }
+                    })
+            });
+            ui.end_row();
+
+            ui.label("Limiter Ceiling (dBTP): ");
+            ui.add_enabled(
+                !state.ui_state.rendering && limiter_enabled && linked,
+                egui::DragValue::new(&mut state.render_settings.dsp_settings.limiter.ceiling_db)
+                    .speed(0.1)
+                    .clamp_range(-20.0..=0.0),
+            );
+            ui.end_row();
+
+            ui.label("Limiter Lookahead (ms): ");
+            ui.add_enabled(
+                !state.ui_state.rendering && limiter_enabled && linked,
+                egui::DragValue::new(&mut state.render_settings.dsp_settings.limiter.lookahead_ms)
+                    .speed(0.1)
+                    .clamp_range(0.1..=50.0),
+            );
+            ui.end_row();
+
             ui.label("Limiter Release (ms): ");
             ui.add_enabled(
                 !state.ui_state.rendering && state.render_settings.dsp_settings.limiter.enabled,
@@ -256,11 +638,101 @@ pub fn show_render_settings(ui: &mut Ui, state: &mut ForteState) {
 
             ui.label("Limiter Attack (ms): ");
             ui.add_enabled(
-                !state.ui_state.rendering && state.render_settings.dsp_settings.limiter.enabled,
+                !state.ui_state.rendering && limiter_enabled,
                 egui::DragValue::new(&mut state.render_settings.dsp_settings.limiter.attack_ms)
                     .speed(0.2)
                     .clamp_range(10..=200),
+            )
+            .on_hover_text(
+                "In linked mode the gain is lowered over this time before a peak. \
+                It can't be longer than the lookahead.",
+            );
+            ui.end_row();
+
+            ui.label("Normalize Loudness: ");
+            ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                ui.checkbox(&mut state.render_settings.dsp_settings.loudness.enabled, "")
+                    .on_hover_text("Measures the whole render first, then applies the gain");
+            });
+            ui.end_row();
+
+            ui.label("Target Loudness (LUFS): ");
+            ui.add_enabled(
+                !state.ui_state.rendering && state.render_settings.dsp_settings.loudness.enabled,
+                egui::DragValue::new(&mut state.render_settings.dsp_settings.loudness.target_lufs)
+                    .speed(0.1)
+                    .clamp_range(-40.0..=0.0),
             );
             ui.end_row();
+
+            ui.label("True Peak Ceiling (dBTP): ");
+            ui.add_enabled(
+                !state.ui_state.rendering && state.render_settings.dsp_settings.loudness.enabled,
+                egui::DragValue::new(
+                    &mut state
+                        .render_settings
+                        .dsp_settings
+                        .loudness
+                        .true_peak_ceiling_db,
+                )
+                .speed(0.1)
+                .clamp_range(-20.0..=0.0),
+            );
+            ui.end_row();
+
+            ui.label("Normalize Peak: ");
+            ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+                ui.checkbox(
+                    &mut state
+                        .render_settings
+                        .dsp_settings
+                        .peak_normalization
+                        .enabled,
+                    "",
+                )
+                .on_hover_text(
+                    "Rescales the finished render so its highest sample hits the target. \
+                    With loudness normalization, the target is used as a ceiling.",
+                );
+            });
+            ui.end_row();
+
+            ui.label("Peak Target (dBFS): ");
+            ui.add_enabled(
+                !state.ui_state.rendering
+                    && state
+                        .render_settings
+                        .dsp_settings
+                        .peak_normalization
+                        .enabled,
+                egui::DragValue::new(
+                    &mut state
+                        .render_settings
+                        .dsp_settings
+                        .peak_normalization
+                        .target_db,
+                )
+                .speed(0.1)
+                .clamp_range(-20.0..=0.0),
+            );
+            ui.end_row();
+        });
+
+    ui.add_space(5.0);
+
+    ui.heading("Effects");
+    ui.label("Applied in order, before the limiter");
+    ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
+        show_effect_chain(ui, "dsp", &mut state.render_settings.dsp_settings.effects);
+    });
+}
+
+fn dither_selector(ui: &mut Ui, dither: &mut DitherMode) {
+    egui::ComboBox::from_id_source("render_dither_selector")
+        .selected_text(format!("{}", dither))
+        .show_ui(ui, |ui| {
+            for d in [DitherMode::None, DitherMode::Tpdf, DitherMode::NoiseShaped] {
+                ui.selectable_value(dither, d, format!("{d}"));
+            }
         });
 }
@@ -1,5 +1,5 @@
-use crate::elements::sf_list::{ForteSFListItem, SFFormat};
 use egui::{Context, Window};
+use forte_core::settings::{ForteSFListItem, SFFormat};
 use xsynth_core::soundfont::Interpolator;
 
 #[derive(Clone)]
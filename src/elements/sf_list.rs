@@ -1,52 +1,18 @@
 use crate::app::add_gui_error;
 use crate::elements::sf_cfg::SoundfontConfigWindow;
-use crate::errors::error_types::FileLoadError;
 use crate::settings::ForteState;
 use egui::{containers::scroll_area::ScrollArea, Context, Ui};
 use egui_extras::{Column, TableBuilder};
 use egui_file::FileDialog;
-use serde::{Deserialize, Serialize};
+use forte_core::errors::error_types::FileLoadError;
+use forte_core::settings::{ForteSFListItem, SFFormat};
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
 use tracing::{info, warn};
 use xsynth_core::soundfont::{Interpolator, SoundfontInitOptions};
-use xsynth_soundfonts::sfz::parse::parse_tokens_resolved;
-
-#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
-pub enum SFFormat {
-    #[default]
-    Sfz,
-}
-
-#[derive(Clone, PartialEq, Eq, Copy, Debug, Serialize, Deserialize)]
-#[serde(remote = "Interpolator")]
-pub enum InterpolatorDef {
-    Nearest,
-    Linear,
-}
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-#[serde(remote = "SoundfontInitOptions")]
-pub struct SoundfontInitOptionsDef {
-    pub bank: Option<u8>,
-    pub preset: Option<u8>,
-    pub linear_release: bool,
-    pub use_effects: bool,
-    #[serde(with = "InterpolatorDef")]
-    pub interpolator: Interpolator,
-}
-
-#[derive(Default, Clone, Serialize, Deserialize)]
-#[serde(default)]
-pub struct ForteSFListItem {
-    pub id: usize,
-    pub enabled: bool,
-    pub selected: bool,
-    pub format: SFFormat,
-    pub path: PathBuf,
-    #[serde(with = "SoundfontInitOptionsDef")]
-    pub init: SoundfontInitOptions,
-}
+use xsynth_soundfonts::sfz::parse_soundfont;
 
 pub struct EguiSFList {
     list: Vec<ForteSFListItem>,
@@ -74,26 +40,23 @@ impl EguiSFList {
             return Err(FileLoadError::FileNotFound);
         }
 
-        if let Some(ext) = path.extension() {
-            if ext == "sfz" {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match ext.as_str() {
+            "sfz" => {
                 info!("Checking soundfont integrity");
-                match parse_tokens_resolved(path.as_path()) {
+                match parse_soundfont(path.as_path()) {
                     Ok(..) => {
-                        let item = ForteSFListItem {
-                            id: self.id_count,
-                            enabled: true,
-                            selected: false,
-                            format: SFFormat::Sfz,
-                            path,
-                            init: SoundfontInitOptions {
-                                bank: Some(0),
-                                preset: Some(0),
-                                interpolator: Interpolator::Linear,
-                                ..Default::default()
-                            },
+                        let init = SoundfontInitOptions {
+                            bank: Some(0),
+                            preset: Some(0),
+                            interpolator: Interpolator::Linear,
+                            ..Default::default()
                         };
-                        self.list.push(item);
-                        self.id_count += 1;
+                        self.push_item(path, SFFormat::Sfz, init);
                         Ok(())
                     }
                     Err(error) => {
@@ -101,16 +64,57 @@ impl EguiSFList {
                         Err(FileLoadError::Corrupt(error.to_string()))
                     }
                 }
-            } else {
+            }
+            "sf2" => {
+                info!("Checking soundfont integrity");
+                Self::check_sf2_header(&path)?;
+
+                // SF2 files usually contain a full bank, so every preset is used by default
+                let init = SoundfontInitOptions {
+                    bank: None,
+                    preset: None,
+                    interpolator: Interpolator::Linear,
+                    ..Default::default()
+                };
+                self.push_item(path, SFFormat::Sf2, init);
+                Ok(())
+            }
+            _ => {
                 warn!("The selected soundfont does not have the correct format");
                 Err(FileLoadError::InvalidFormat)
             }
-        } else {
-            warn!("The selected soundfont does not have the correct format");
-            Err(FileLoadError::InvalidFormat)
         }
     }
 
+    fn push_item(&mut self, path: PathBuf, format: SFFormat, init: SoundfontInitOptions) {
+        let item = ForteSFListItem {
+            id: self.id_count,
+            enabled: true,
+            selected: false,
+            format,
+            path,
+            init,
+        };
+        self.list.push(item);
+        self.id_count += 1;
+    }
+
+    fn check_sf2_header(path: &Path) -> Result<(), FileLoadError> {
+        let mut header = [0u8; 12];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .map_err(|error| {
+                warn!("Unable to read the selected soundfont: {error}");
+                FileLoadError::Corrupt(error.to_string())
+            })?;
+
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"sfbk" {
+            warn!("The selected soundfont is not a valid SF2 file");
+            return Err(FileLoadError::Corrupt("Invalid SF2 header".to_owned()));
+        }
+        Ok(())
+    }
+
     pub fn select_all(&mut self) {
         self.list = self
             .list
@@ -216,7 +220,8 @@ impl EguiSFList {
                 if ui.button("Add Soundfont").clicked() {
                     let filter = |path: &Path| {
                         if let Some(path) = path.to_str() {
-                            path.ends_with(".sfz")
+                            let path = path.to_lowercase();
+                            path.ends_with(".sfz") || path.ends_with(".sf2")
                         } else {
                             false
                         }
@@ -238,7 +243,7 @@ impl EguiSFList {
                     self.clear();
                 }
                 ui.label("Loading order is top to bottom.");
-                ui.label("Supported formats: SFZ");
+                ui.label("Supported formats: SFZ, SF2");
 
                 if let Some(dialog) = &mut self.file_dialog {
                     if dialog.show(ctx).selected() {
@@ -336,6 +341,7 @@ impl EguiSFList {
                             row.col(|ui| {
                                 ui.label(match item.format {
                                     SFFormat::Sfz => "SFZ",
+                                    SFFormat::Sf2 => "SF2",
                                 });
                             });
 
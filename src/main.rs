@@ -1,14 +1,11 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod app;
-mod dsp;
+mod cli;
 mod elements;
-mod errors;
 mod settings;
 mod tabs;
 mod utils;
-mod writer;
-mod xsynth;
 
 use std::backtrace::Backtrace;
 use std::fs::{remove_file, File};
@@ -47,6 +44,16 @@ fn load_icon() -> eframe::IconData {
     }
 }
 
+// Release builds use the windows subsystem and start without a console, so the
+// headless output is sent to the console Forte was started from
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
 fn main() {
     panic::set_hook(Box::new(panic_hook));
 
@@ -63,6 +70,13 @@ fn main() {
     )
     .expect("Unable to set global tracing subscriber");
 
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg == "--headless").unwrap_or(false) {
+        #[cfg(windows)]
+        attach_console();
+        std::process::exit(cli::run_headless(&args[2..]));
+    }
+
     let native_options = eframe::NativeOptions {
         icon_data: Some(load_icon()),
         initial_window_size: Some(egui::Vec2::new(900.0, 600.0)),
@@ -5,10 +5,11 @@ use crate::app::add_gui_error;
 use crate::elements::{midi_list::EguiMIDIList, render_settings::show_render_settings};
 use crate::settings::ForteState;
 use crate::utils::render_in_frame;
-use crate::xsynth::{ManagerStatus, RenderThreadManager};
 use tracing::{error, info};
 
 use egui_file::FileDialog;
+use forte_core::midi::has_midi_extension;
+use forte_core::xsynth::{MIDIInput, MIDIRendererStatus, ManagerStatus, RenderThreadManager};
 use std::path::Path;
 
 pub struct ForteRenderTab {
@@ -54,17 +55,36 @@ impl ForteRenderTab {
                     error!("Invalid Soundfont chain. Aborting render.");
                     add_gui_error(
                         "Soundfont Loader Error".to_owned(),
-                        "Invalid Soundfont chain".to_owned(),
+                        mgr.soundfont_error()
+                            .unwrap_or_else(|| "Invalid Soundfont chain".to_owned()),
                     );
                 } else if status == ManagerStatus::SoundfontsFinished {
                     info!("Starting export");
-                    mgr.render(state);
+                    mgr.render();
                     ended = false;
                 } else if status == ManagerStatus::RenderingMIDIs {
                     mgr.spawn_next();
                     ended = false;
                 } else if status == ManagerStatus::RenderFinished && !mgr.spawn_next() {
                     info!("Conversion finished");
+                    let results = mgr.get_statuses().into_iter().zip(mgr.get_errors());
+                    for (item, (status, error)) in self.midi_list.iter_list().zip(results) {
+                        if status == MIDIRendererStatus::Error {
+                            let title = if let Some(filen) = item.path.file_name() {
+                                format!(
+                                    "There was an error rendering \"{}\".",
+                                    filen.to_string_lossy()
+                                )
+                            } else {
+                                "There was an error rendering the selected MIDI.".to_string()
+                            };
+                            add_gui_error(
+                                title,
+                                error
+                                    .unwrap_or_else(|| "Unable to write the audio file".to_owned()),
+                            );
+                        }
+                    }
                     state.ui_state.rendering = false;
                     mgr.cancel_all();
                 }
@@ -102,14 +122,8 @@ impl ForteRenderTab {
                             ui.add_enabled_ui(!state.ui_state.rendering, |ui| {
                                 ui.horizontal(|ui| {
                                     if ui.add(egui::Button::new("Add MIDI").min_size(egui::Vec2::new(rect.width() / 2.0 - 5.0, 18.0))).clicked() {
-                                        let filter = |path: &Path| {
-                                            if let Some(path) = path.to_str() {
-                                                path.ends_with(".mid")
-                                            } else {
-                                                false
-                                            }
-                                        };
-                                        let filter = Box::new(filter);
+                                        // Checking the content of every file in the dialog would be too slow
+                                        let filter = Box::new(has_midi_extension);
 
                                         let mut dialog = FileDialog::open_file(state.ui_state.midi_select_last_path.clone())
                                             .resizable(true)
@@ -200,11 +214,11 @@ impl ForteRenderTab {
                                                     state.ui_state.rendering = true;
                                                     state.render_settings.output_dir = Some(path);
 
-                                                    let midis = self.midi_list.iter_list().map(|item| item.path).collect();
+                                                    let midis = self.midi_list.iter_list().map(|item| MIDIInput { path: item.path, length: Some(item.length), source: Some(item.source) }).collect();
 
                                                     info!("Loading soundfonts");
 
-                                                    match RenderThreadManager::new(state, midis) {
+                                                    match RenderThreadManager::new(&state.config(), midis) {
                                                         Ok(m) => self.render_manager = Some(m),
                                                         Err(err) => {
                                                             state.ui_state.rendering = false;
@@ -238,7 +252,9 @@ impl ForteRenderTab {
         if state.ui_state.render_settings_visible {
             egui::SidePanel::right("render_settings")
                 .resizable(false)
-                .show_inside(ui, |ui| show_render_settings(ui, state));
+                .show_inside(ui, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| show_render_settings(ui, state));
+                });
         }
 
         egui::CentralPanel::default().show_inside(ui, |ui| {
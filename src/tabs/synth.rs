@@ -2,7 +2,7 @@ use crate::elements::{channel_cfg::EguiChannelConfig, sf_list::EguiSFList};
 use crate::settings::ForteState;
 use crate::utils::render_in_frame;
 use egui::{Context, Ui};
-use serde::{Deserialize, Serialize};
+use forte_core::settings::{SingleChannelSettings, SynthCfgType, CHANNELS_PER_PORT};
 
 #[derive(PartialEq, Eq, Default)]
 enum Panel {
@@ -11,13 +11,6 @@ enum Panel {
     Settings,
 }
 
-#[derive(PartialEq, Eq, Default, Clone, Copy, Serialize, Deserialize)]
-pub enum SynthCfgType {
-    #[default]
-    Global,
-    PerChannel,
-}
-
 pub struct ForteSynthTab {
     current_panel: Panel,
 
@@ -32,22 +25,22 @@ pub struct ForteSynthTab {
 
 impl ForteSynthTab {
     pub fn new(state: &ForteState) -> Self {
+        // Configs that are missing some channels are filled up to whole ports
+        let channels = state.synth_settings.configured_ports() * CHANNELS_PER_PORT;
+        let individual: Vec<SingleChannelSettings> = (0..channels)
+            .map(|i| state.synth_settings.individual(i))
+            .collect();
+
         let mut sf_split_lists = Vec::new();
-        for i in 0..16 {
-            sf_split_lists.push(EguiSFList::new(
-                state.synth_settings.individual_settings[i]
-                    .soundfonts
-                    .clone(),
-            ));
+        for ch in individual.iter() {
+            sf_split_lists.push(EguiSFList::new(ch.soundfonts.clone()));
         }
         let sf_global_list =
             EguiSFList::new(state.synth_settings.global_settings.soundfonts.clone());
 
         let mut channel_cfgs = Vec::new();
-        for i in 0..16 {
-            channel_cfgs.push(EguiChannelConfig::new(
-                &state.synth_settings.individual_settings[i],
-            ));
+        for ch in individual.iter() {
+            channel_cfgs.push(EguiChannelConfig::new(ch));
         }
         let channel_cfg_global = EguiChannelConfig::new(&state.synth_settings.global_settings);
 
@@ -62,6 +55,36 @@ impl ForteSynthTab {
         }
     }
 
+    fn ports(&self) -> usize {
+        self.channel_cfgs.len() / CHANNELS_PER_PORT
+    }
+
+    // Ports share the soundfont and channel setting lists, so both are resized together
+    fn show_port_buttons(&mut self, ui: &mut Ui) {
+        if ui
+            .button("\u{2795} Add Port")
+            .on_hover_text("Adds 16 channels for MIDIs that use more than one MIDI port")
+            .clicked()
+        {
+            let settings = SingleChannelSettings::default();
+            for _ in 0..CHANNELS_PER_PORT {
+                self.sf_split_lists
+                    .push(EguiSFList::new(settings.soundfonts.clone()));
+                self.channel_cfgs.push(EguiChannelConfig::new(&settings));
+            }
+        }
+        if ui
+            .add_enabled(self.ports() > 1, egui::Button::new("\u{2796} Remove Port"))
+            .clicked()
+        {
+            let len = self.channel_cfgs.len() - CHANNELS_PER_PORT;
+            self.sf_split_lists.truncate(len);
+            self.channel_cfgs.truncate(len);
+            self.sf_split_selected = self.sf_split_selected.min(len - 1);
+            self.channel_cfg_selected = self.channel_cfg_selected.min(len - 1);
+        }
+    }
+
     pub fn show(&mut self, ui: &mut Ui, state: &mut ForteState, ctx: &Context) {
         ui.horizontal(|ui| {
             ui.heading("Synthesizer Configuration");
@@ -94,19 +117,15 @@ impl ForteSynthTab {
                     );
                     if state.synth_settings.sfcfg_type == SynthCfgType::PerChannel {
                         ui.separator();
-                        let mut chvec: Vec<String> = Vec::new();
-                        for i in 1..=16 {
-                            chvec.push(format!("Channel {i}"));
-                        }
-
-                        ui.horizontal(|ui| {
-                            egui::ComboBox::from_id_source("sf_split_selector").show_index(
-                                ui,
-                                &mut self.sf_split_selected,
-                                16,
-                                |i| chvec[i].clone(),
-                            );
-                        });
+                        let ports = self.ports();
+                        show_channel_selector(
+                            ui,
+                            "sf_split_selector",
+                            &mut self.sf_split_selected,
+                            ports,
+                        );
+                        ui.separator();
+                        self.show_port_buttons(ui);
                     }
                 });
                 ui.add_space(5.0);
@@ -135,32 +154,32 @@ impl ForteSynthTab {
                     );
                     if state.synth_settings.chcfg_type == SynthCfgType::PerChannel {
                         ui.separator();
-                        let mut chvec: Vec<String> = Vec::new();
-                        for i in 1..=16 {
-                            chvec.push(format!("Channel {i}"));
-                        }
-
-                        ui.horizontal(|ui| {
-                            egui::ComboBox::from_id_source("channel_cfg_selector").show_index(
-                                ui,
-                                &mut self.channel_cfg_selected,
-                                16,
-                                |i| chvec[i].clone(),
-                            );
-                        });
+                        let ports = self.ports();
+                        show_channel_selector(
+                            ui,
+                            "channel_cfg_selector",
+                            &mut self.channel_cfg_selected,
+                            ports,
+                        );
+                        ui.separator();
+                        self.show_port_buttons(ui);
                     }
                 });
                 ui.add_space(5.0);
 
                 if state.synth_settings.chcfg_type == SynthCfgType::Global {
                     render_in_frame(ui, |ui| {
-                        self.channel_cfg_global.show(ui);
-                        ui.allocate_space(ui.available_size());
+                        egui::ScrollArea::vertical().show(ui, |ui| {
+                            self.channel_cfg_global.show(ui);
+                            ui.allocate_space(ui.available_size());
+                        });
                     });
                 } else {
                     render_in_frame(ui, |ui| {
-                        self.channel_cfgs[self.channel_cfg_selected].show(ui);
-                        ui.allocate_space(ui.available_size());
+                        egui::ScrollArea::vertical().show(ui, |ui| {
+                            self.channel_cfgs[self.channel_cfg_selected].show(ui);
+                            ui.allocate_space(ui.available_size());
+                        });
                     });
                 }
             }
@@ -172,16 +191,40 @@ impl ForteSynthTab {
 
     pub fn apply_to_state(&self, state: &mut ForteState) {
         state.synth_settings.global_settings.soundfonts = self.sf_global_list.iter_list().collect();
-        for i in 0..16 {
-            state.synth_settings.individual_settings[i].soundfonts =
-                self.sf_split_lists[i].iter_list().collect();
+        state
+            .synth_settings
+            .individual_settings
+            .resize(self.channel_cfgs.len(), SingleChannelSettings::default());
+        for (i, list) in self.sf_split_lists.iter().enumerate() {
+            state.synth_settings.individual_settings[i].soundfonts = list.iter_list().collect();
         }
 
         self.channel_cfg_global
             .save_to_state_settings(&mut state.synth_settings.global_settings);
-        for i in 0..16 {
-            self.channel_cfgs[i]
-                .save_to_state_settings(&mut state.synth_settings.individual_settings[i]);
+        for (i, cfg) in self.channel_cfgs.iter().enumerate() {
+            cfg.save_to_state_settings(&mut state.synth_settings.individual_settings[i]);
         }
     }
 }
+
+// Selects one channel out of all ports, `selected` is `port * 16 + channel`
+fn show_channel_selector(ui: &mut Ui, id: &str, selected: &mut usize, ports: usize) {
+    let mut port = *selected / CHANNELS_PER_PORT;
+    let mut channel = *selected % CHANNELS_PER_PORT;
+
+    ui.horizontal(|ui| {
+        if ports > 1 {
+            egui::ComboBox::from_id_source(format!("{id}_port")).show_index(
+                ui,
+                &mut port,
+                ports,
+                |i| format!("Port {}", i + 1),
+            );
+        }
+        egui::ComboBox::from_id_source(id).show_index(ui, &mut channel, CHANNELS_PER_PORT, |i| {
+            format!("Channel {}", i + 1)
+        });
+    });
+
+    *selected = port * CHANNELS_PER_PORT + channel;
+}
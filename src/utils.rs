@@ -3,11 +3,14 @@ use egui::Ui;
 use reqwest::blocking::ClientBuilder;
 use serde_json::Value;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env::consts::{ARCH, OS},
 };
 use tracing::info;
 
+// The track count in the MIDI header is 16 bits
+const MAX_TRACK: usize = u16::MAX as usize;
+
 pub fn set_button_spacing(ui: &mut Ui) {
     ui.spacing_mut().button_padding = (6.0, 3.0).into();
     ui.visuals_mut().widgets.inactive.rounding = egui::Rounding::same(5.0);
@@ -35,6 +38,85 @@ pub fn bytes_to_filesize_str(size: u64) -> String {
     }
 }
 
+// Track groups are written like "1-4; 5, 7", with the groups separated by semicolons
+pub fn track_groups_to_str(groups: &[Vec<usize>]) -> String {
+    groups
+        .iter()
+        .map(|group| {
+            // Consecutive tracks are collapsed back into ranges
+            let mut items: Vec<(usize, usize)> = Vec::new();
+            for &track in group {
+                match items.last_mut() {
+                    Some((_, end)) if *end + 1 == track => *end = track,
+                    _ => items.push((track, track)),
+                }
+            }
+            items
+                .iter()
+                .map(|(start, end)| {
+                    if start == end {
+                        start.to_string()
+                    } else {
+                        format!("{start}-{end}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn parse_track(text: &str, item: &str) -> Result<usize, String> {
+    match text.trim().parse::<usize>() {
+        Ok(0) => Err("Tracks are counted from 1".to_owned()),
+        Ok(track) if track > MAX_TRACK => Err(format!("Track {track} is above {MAX_TRACK}")),
+        Ok(track) => Ok(track),
+        Err(..) => Err(format!("\"{item}\" is not a track or a range")),
+    }
+}
+
+/// Parses track groups written like "1-4; 5, 7". An empty text means no groups, so
+/// every track gets its own stem, which is only allowed for MIDIs with up to 64 tracks.
+pub fn str_to_track_groups(text: &str) -> Result<Vec<Vec<usize>>, String> {
+    let mut groups = Vec::new();
+    if text.trim().is_empty() {
+        return Ok(groups);
+    }
+
+    let mut seen = HashSet::new();
+    for (i, group) in text.split(';').enumerate() {
+        if group.trim().is_empty() {
+            return Err(format!("Group {} is empty", i + 1));
+        }
+
+        let mut tracks = Vec::new();
+        for item in group.split(',').map(str::trim) {
+            if item.is_empty() {
+                return Err(format!("Group {} has an empty entry", i + 1));
+            }
+            match item.split_once('-') {
+                Some((start, end)) => {
+                    let start = parse_track(start, item)?;
+                    let end = parse_track(end, item)?;
+                    if end < start {
+                        return Err(format!("The range {item} ends before it starts"));
+                    }
+                    tracks.extend(start..=end);
+                }
+                None => tracks.push(parse_track(item, item)?),
+            }
+        }
+
+        if let Some(track) = tracks.iter().find(|&&track| !seen.insert(track)) {
+            return Err(format!("Track {track} is in more than one place"));
+        }
+        groups.push(tracks);
+    }
+
+    Ok(groups)
+}
+
 pub fn render_in_frame<E>(ui: &mut Ui, resp: E)
 where
     E: FnOnce(&mut Ui),
@@ -104,3 +186,60 @@ pub fn check_for_updates() {
         add_update_message(latest, url, body)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_groups_parse() {
+        assert_eq!(str_to_track_groups(""), Ok(vec![]));
+        assert_eq!(str_to_track_groups("  "), Ok(vec![]));
+        assert_eq!(
+            str_to_track_groups("1-4; 5, 7"),
+            Ok(vec![vec![1, 2, 3, 4], vec![5, 7]])
+        );
+        assert_eq!(
+            str_to_track_groups(" 1 - 3 ,5 ;8 "),
+            Ok(vec![vec![1, 2, 3, 5], vec![8]])
+        );
+        assert_eq!(str_to_track_groups("2-2"), Ok(vec![vec![2]]));
+    }
+
+    #[test]
+    fn track_groups_round_trip() {
+        let groups = str_to_track_groups("1-4; 5, 7; 9-10, 12").unwrap();
+        assert_eq!(track_groups_to_str(&groups), "1-4; 5, 7; 9-10, 12");
+    }
+
+    #[test]
+    fn track_groups_reject_duplicates() {
+        assert!(str_to_track_groups("1, 1").is_err());
+        assert!(str_to_track_groups("1-3; 2").is_err());
+        assert!(str_to_track_groups("1-3, 3-5").is_err());
+    }
+
+    #[test]
+    fn track_groups_reject_empty_groups() {
+        assert!(str_to_track_groups("1;;2").is_err());
+        assert!(str_to_track_groups("1; ").is_err());
+        assert!(str_to_track_groups("1,,2").is_err());
+        assert!(str_to_track_groups("1,").is_err());
+    }
+
+    #[test]
+    fn track_groups_reject_out_of_range() {
+        assert!(str_to_track_groups("0").is_err());
+        assert!(str_to_track_groups("0-3").is_err());
+        assert!(str_to_track_groups("65536").is_err());
+        assert!(str_to_track_groups("1-100000").is_err());
+        assert!(str_to_track_groups("4-1").is_err());
+    }
+
+    #[test]
+    fn track_groups_reject_invalid_text() {
+        for text in ["a", "1-", "-3", "1 2", "1-2-3", "1.5"] {
+            assert!(str_to_track_groups(text).is_err(), "{text}");
+        }
+    }
+}
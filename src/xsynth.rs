@@ -1,6 +0,0 @@
-pub mod renderers;
-
-mod render_manager;
-pub use render_manager::*;
-mod midi_pool;
-mod soundfont_pool;